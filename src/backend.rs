@@ -0,0 +1,344 @@
+//! A [`Backend`] is whatever a [`crate::FilesystemConfig`] actually reads from.
+//!
+//! ORO Filesystem comes with two backends: the native filesystem and
+//! indexed filesystems (Asset Packages and Aura files). Any other source,
+//! like a custom archive format or an in-memory fake for tests, can be
+//! plugged in by implementing [`Backend`] and passing it to
+//! [`crate::FilesystemConfig::with_backend`].
+
+use std::{fmt::Debug, io::{Read, Seek}};
+
+use crate::{config::assetmap::AssetMap, FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult, FilesystemType};
+#[cfg(feature = "aura")]
+use crate::readers::transfer::Transfer;
+
+/// Anything that can be read and seeked. Returned by [`Backend::open`].
+///
+/// This trait is implemented automatically for every type that implements
+/// [`Read`], [`Seek`] and [`Send`].
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// The kind of source that serves a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// The native filesystem
+    Native,
+    /// An Obstruction Asset Package
+    AssetPack,
+    /// A web resource from an Aura file
+    Aura,
+    /// A directory that doesn't exist in any source. Directories of indexed
+    /// filesystems only exist because files are indexed inside them.
+    Virtual,
+    /// A user-provided [`Backend`]
+    #[default]
+    Custom
+}
+
+/// A boxed [`Future`](std::future::Future) returned by the asynchronous methods of [`Backend`]
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// Information about a file or directory in a [`Backend`]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    /// Size of the file in bytes. [`None`] for directories and for
+    /// files whose size can't be known without reading them (Aura files).
+    pub size: Option<u64>,
+    /// Whether the path is a directory or not
+    pub is_dir: bool,
+    /// The kind of source that serves this file or directory
+    pub kind: BackendKind,
+    /// Asset Package that contains the file. Only present in [`BackendKind::AssetPack`] files.
+    pub package: Option<String>,
+    /// Index of the first byte of the file in its Asset Package.
+    /// Only present in [`BackendKind::AssetPack`] files.
+    pub offset: Option<u64>,
+    /// URL of the file. Only present in [`BackendKind::Aura`] files.
+    pub url: Option<String>,
+    /// Hash of the file, if the index has one. Only present in [`BackendKind::Aura`] files.
+    pub hash: Option<String>
+}
+
+/// An entry in a directory listing obtained from [`Backend::read_dir`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DirEntry {
+    /// Full path from the virtual root to this entry
+    pub path: String,
+    /// Name of the file or directory (the last component of `path`)
+    pub name: String,
+    /// Whether this entry is a directory or not
+    pub is_dir: bool
+}
+
+/// The result of prefetching one path with [`Backend::prefetch`]
+#[derive(Debug)]
+pub struct PrefetchEntry {
+    /// The path, as it was passed to [`Backend::prefetch`]
+    pub path: String,
+    /// Whether the file is ready to be read without waiting for the network
+    pub result: FilesystemResult<()>
+}
+
+/// What [`Backend::prefetch`] did with every path, in the same order
+/// in which the paths were passed.
+#[derive(Debug, Default)]
+pub struct PrefetchReport {
+    pub entries: Vec<PrefetchEntry>
+}
+
+impl PrefetchReport {
+    /// Returns `true` if every path was prefetched successfully
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(|entry| entry.result.is_ok())
+    }
+
+    /// Returns the paths that were prefetched successfully
+    pub fn succeeded(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter(|entry| entry.result.is_ok()).map(|entry| entry.path.as_str())
+    }
+
+    /// Returns the paths that couldn't be prefetched and why
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &FilesystemError)> {
+        self.entries.iter().filter_map(|entry| entry.result.as_ref().err().map(|e| (entry.path.as_str(), e)))
+    }
+
+    /// Prefetches paths that are served by other configurations, like the layers
+    /// of an [`crate::Overlay`]. `resolve` returns the configuration that serves a
+    /// path and the path inside that configuration.
+    /// 
+    /// Paths are grouped by configuration, so each configuration prefetches
+    /// all of its paths at once.
+    pub(crate) fn from_configs<'a>(paths: &[&str], resolve: impl Fn(&str) -> FilesystemResult<(&'a FilesystemConfig, String)>) -> Self {
+        let mut results: Vec<Option<FilesystemResult<()>>> = paths.iter().map(|_| None).collect();
+        let mut groups: Vec<(&FilesystemConfig, Vec<(usize, String)>)> = Vec::new();
+        for (position, path) in paths.iter().enumerate() {
+            match resolve(path) {
+                Ok((config, inner_path)) => match groups.iter_mut().find(|(group, _)| std::ptr::eq(*group, config)) {
+                    Some((_, group_paths)) => group_paths.push((position, inner_path)),
+                    None => groups.push((config, vec![(position, inner_path)])),
+                },
+                Err(e) => results[position] = Some(Err(e)),
+            }
+        }
+
+        for (config, group_paths) in groups {
+            let inner_paths: Vec<&str> = group_paths.iter().map(|(_, path)| path.as_str()).collect();
+            let report = config.backend().prefetch(&inner_paths);
+            for ((position, _), entry) in group_paths.into_iter().zip(report.entries) {
+                results[position] = Some(entry.result);
+            }
+        }
+
+        PrefetchReport {
+            entries: paths.iter().zip(results).map(|(path, result)| PrefetchEntry {
+                path: path.to_string(),
+                result: result.unwrap_or(Ok(()))
+            }).collect()
+        }
+    }
+}
+
+/// A source of files for a Virtual Filesystem.
+///
+/// All paths passed to a backend are virtual paths, relative to
+/// the root of the Virtual Filesystem.
+pub trait Backend: Debug + Send + Sync {
+    /// Reads the file in the indicated path and returns its contents as binary
+    fn read(&self, path: &str) -> FilesystemResult<Vec<u8>>;
+
+    /// Reads the file in the indicated path and returns its contents as a string.
+    ///
+    /// By default this reads the file as binary and checks that it's valid UTF-8.
+    fn read_to_string(&self, path: &str) -> FilesystemResult<String> {
+        let buffer = self.read(path)?;
+        String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))
+    }
+
+    /// Same as [`Backend::read`], but downloads are reported to the progress
+    /// observers of `transfer` and stopped by its cancellation tokens.
+    ///
+    /// By default this ignores `transfer` and calls [`Backend::read`].
+    /// Backends that download files should override it.
+    #[cfg(feature = "aura")]
+    fn read_with(&self, path: &str, _transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+        self.read(path)
+    }
+
+    /// Same as [`Backend::read_to_string`], but downloads are reported to the
+    /// progress observers of `transfer` and stopped by its cancellation tokens.
+    ///
+    /// By default this calls [`Backend::read_with`] and checks that the
+    /// file is valid UTF-8.
+    #[cfg(feature = "aura")]
+    fn read_to_string_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<String> {
+        let buffer = self.read_with(path, transfer)?;
+        String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))
+    }
+
+    /// Asynchronous version of [`Backend::read`].
+    ///
+    /// By default this calls [`Backend::read`], which blocks the executor.
+    /// Backends that can read without blocking should override it.
+    #[cfg(feature = "async")]
+    fn read_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<Vec<u8>>> {
+        Box::pin(async move { self.read(path) })
+    }
+
+    /// Asynchronous version of [`Backend::read_to_string`].
+    ///
+    /// By default this calls [`Backend::read_async`] and checks that the
+    /// file is valid UTF-8.
+    #[cfg(feature = "async")]
+    fn read_to_string_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<String>> {
+        Box::pin(async move {
+            let buffer = self.read_async(path).await?;
+            String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))
+        })
+    }
+
+    /// Opens the file in the indicated path for reading.
+    fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>>;
+
+    /// Returns the [`Metadata`] of a file or directory
+    fn metadata(&self, path: &str) -> FilesystemResult<Metadata>;
+
+    /// Lists the contents of a directory. An empty path is the root
+    /// of the Virtual Filesystem.
+    ///
+    /// Entries are sorted by name.
+    fn read_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>>;
+
+    /// Lists the contents of a directory and all of its subdirectories.
+    ///
+    /// Directories are listed before their contents. By default this calls
+    /// [`Backend::read_dir`] recursively.
+    fn walk_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in self.read_dir(path)? {
+            let is_dir = entry.is_dir;
+            let entry_path = entry.path.clone();
+            entries.push(entry);
+            if is_dir {
+                entries.append(&mut self.walk_dir(&entry_path)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Gets files ready to be read without waiting for the network, and
+    /// reports what happened with every path.
+    /// 
+    /// By default there's nothing to download, so this only checks that
+    /// every path exists. Backends that read from the network should override it.
+    fn prefetch(&self, paths: &[&str]) -> PrefetchReport {
+        PrefetchReport {
+            entries: paths.iter().map(|path| PrefetchEntry {
+                path: path.to_string(),
+                result: self.metadata(path).map(|_| ())
+            }).collect()
+        }
+    }
+
+    /// Returns the type of filesystem this backend reads from.
+    /// [`FilesystemType::Custom`] by default.
+    fn fs_type(&self) -> FilesystemType {
+        FilesystemType::Custom
+    }
+
+    /// Called when the [`FilesystemOptions`] of the configuration that holds
+    /// this backend change. Backends that don't use any option can ignore them,
+    /// which is what this does by default.
+    fn set_options(&mut self, _options: &FilesystemOptions) {}
+
+    /// Returns the [`AssetMap`] of this backend if it is indexed.
+    /// [`None`] by default.
+    fn asset_map(&self) -> Option<&AssetMap> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, io::{Cursor, Read}};
+
+    use crate::{read, read_to_string, Backend, BackendKind, DirEntry, FilesystemConfig, FilesystemError, FilesystemResult, FilesystemType, Metadata, ReadSeek};
+
+    /// A backend that serves files from memory
+    #[derive(Debug)]
+    struct MemoryBackend {
+        files: BTreeMap<String, Vec<u8>>
+    }
+
+    impl Backend for MemoryBackend {
+        fn read(&self, path: &str) -> FilesystemResult<Vec<u8>> {
+            self.files.get(path).cloned().ok_or(FilesystemError::NotFound(path.to_string()))
+        }
+        fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+            Ok(Box::new(Cursor::new(self.read(path)?)))
+        }
+        fn metadata(&self, path: &str) -> FilesystemResult<Metadata> {
+            let file = self.read(path)?;
+            Ok(Metadata { size: Some(file.len() as u64), ..Default::default() })
+        }
+        fn read_dir(&self, _path: &str) -> FilesystemResult<Vec<DirEntry>> {
+            Ok(self.files.keys().map(|name| DirEntry { path: name.clone(), name: name.clone(), is_dir: false }).collect())
+        }
+    }
+
+    #[test]
+    fn read_from_custom_backend() -> FilesystemResult<()> {
+        let mut files = BTreeMap::new();
+        files.insert("hello.txt".to_string(), b"Hello, World!".to_vec());
+        let config = FilesystemConfig::with_backend(MemoryBackend { files });
+
+        assert_eq!(config.fs_type(), FilesystemType::Custom);
+        assert_eq!(read_to_string("hello.txt", &config)?, "Hello, World!");
+        assert_eq!(read("hello.txt", &config)?.len(), 13);
+        assert!(read("missing.txt", &config).is_err());
+
+        let mut contents = String::new();
+        config.backend().open("hello.txt")?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hello, World!");
+        Ok(())
+    }
+
+    #[test]
+    fn list_indexed_backend() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        let root: Vec<(String, bool)> = config.backend().read_dir("")?
+            .into_iter()
+            .map(|entry| (entry.path, entry.is_dir))
+            .collect();
+        assert_eq!(root, vec![
+            ("binaries".to_string(), true),
+            ("otherFolder".to_string(), true),
+            ("virtualFolder".to_string(), true)
+        ]);
+
+        let folder: Vec<String> = config.backend().read_dir("virtualFolder")?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(folder, vec!["virtualFolder/vfile1-copy.txt".to_string(), "virtualFolder/vfile1.txt".to_string()]);
+
+        assert!(matches!(config.backend().read_dir("invented"), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(config.backend().read_dir("binaries/example.bin"), Err(FilesystemError::NotADirectory(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn stat_indexed_backend() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        assert_eq!(config.backend().metadata("binaries/example.bin")?, Metadata {
+            size: Some(13),
+            kind: BackendKind::AssetPack,
+            package: Some("package.oap".to_string()),
+            offset: Some(113),
+            ..Default::default()
+        });
+        assert_eq!(config.backend().metadata("binaries")?, Metadata { is_dir: true, kind: BackendKind::Virtual, ..Default::default() });
+        Ok(())
+    }
+}
@@ -1,49 +1,123 @@
-use std::{fs, path::{Path}};
+use std::{collections::HashMap, fs, path::Path};
 
-use crate::{config::{assetmap::AssetMap, index::{IndexFile, IndexType}}, FilesystemError, FilesystemResult};
+use crate::{backend::Backend, config::{assetmap::AssetMap, index::{IndexFile, IndexType}, options::FilesystemOptions, secure_path::BoundChecker}, readers::{filesystem::NativeBackend, indexed::IndexedBackend}, FilesystemError, FilesystemResult};
+#[cfg(feature = "aura")]
+use crate::readers::{aura, http::HttpClient};
 
 pub mod index;
 pub mod assetmap;
 pub mod secure_path;
+pub mod virtual_path;
+pub mod options;
 
 /// Type of filesystem that we want to access.
 #[derive(Debug, PartialEq)]
 pub enum FilesystemType {
     Filesystem,
-    Indexed
+    Indexed,
+    /// A user-provided [`Backend`]
+    Custom
 }
 
 /// Configuration for Obstruction Filesystem. It has three attributes:
 /// - `path`: Relative (starting at executable's directory) path to the "root" of the virtual filesystem. `./` by default.
-/// - `index`: The deserialized indices file. None in [`FilesystemType::Filesystem`] configurations, Some in any Indexed configuration (Aura or AssetPackage)
+/// - `backend`: The [`Backend`] that files are read from. A [`NativeBackend`] in [`FilesystemType::Filesystem`] configurations,
+///   an [`IndexedBackend`] in any Indexed configuration (Aura or AssetPackage)
+/// - `options`: The [`FilesystemOptions`] passed to the backend, like the cache directory for Aura files.
 #[derive(Debug)]
 pub struct FilesystemConfig {
     path: String,
-    index: Option<AssetMap>
+    backend: Box<dyn Backend>,
+    options: FilesystemOptions,
+    /// `true` if the options were set on this configuration, instead of
+    /// inherited from an [`crate::Overlay`] or [`crate::MountTable`] that contains it
+    own_options: bool
 }
 
 impl FilesystemConfig {
     /// Constructs a default config with nothing as the root and an
     /// automatically detected filesystem type.
     pub fn new() -> FilesystemResult<Self> {
-        let root = Self::get_usable_root("");
-        let index = Self::autodetect_filesystem(&root)?;
-        Ok(FilesystemConfig { path: root, index })
+        Self::with_root("")
     }
     /// Constructs a configuration object with a root and an
     /// automatically detected filesystem type.
     pub fn with_root(root: &str) -> FilesystemResult<Self> {
         let root = Self::get_usable_root(root);
-        let index = Self::autodetect_filesystem(&root)?;
-        Ok(FilesystemConfig { path: root, index })
+        let backend: Box<dyn Backend> = match Self::autodetect_filesystem(&root)? {
+            Some(index) => Box::new(IndexedBackend::new(&root, index)),
+            None => Box::new(NativeBackend::new(&root)?),
+        };
+        Ok(FilesystemConfig { path: root, backend, options: FilesystemOptions::default(), own_options: false })
+    }
+    /// Constructs an indexed configuration object with a root and one specific
+    /// index file inside that root. Any other `*.oroi` file in the root is ignored.
+    /// 
+    /// Index files outside the root (with `..`, absolute paths or symlinks) are
+    /// rejected with [`FilesystemError::OutOfBounds`].
+    pub fn with_index(root: &str, index_name: &str) -> FilesystemResult<Self> {
+        let root = Self::get_usable_root(root);
+        let index_path = BoundChecker::new(Path::new(&root))?.ensure_in_bounds(&Path::new(&root).join(index_name))?;
+        let index = Self::load_index(&index_path)?;
+        Ok(FilesystemConfig { path: root.clone(), backend: Box::new(IndexedBackend::new(&root, index)), options: FilesystemOptions::default(), own_options: false })
+    }
+    /// Constructs an indexed configuration object from an index file on the web.
+    /// 
+    /// Relative Aura URLs and Asset Package paths in the index are resolved
+    /// against `url` (see [`IndexFile::resolve_urls`]), so nothing has to exist
+    /// on disk. The path of this configuration is `url`.
+    #[cfg(feature = "aura")]
+    pub fn from_index_url(url: &str) -> FilesystemResult<Self> {
+        Self::from_index_url_with_options(url, FilesystemOptions::default())
+    }
+    /// Same as [`FilesystemConfig::from_index_url`], but the index itself is already
+    /// downloaded with `options`. This is needed if the index is in a host
+    /// that requires authentication, or to read it from the cache in offline mode.
+    #[cfg(feature = "aura")]
+    pub fn from_index_url_with_options(url: &str, options: FilesystemOptions) -> FilesystemResult<Self> {
+        let client = HttpClient::new(options.http());
+        let mut index_file = aura::read_to_string(&client, url, None, &options)?.parse::<IndexFile>()?;
+        index_file.resolve_urls(url)?;
+        let index = AssetMap::try_from(index_file)?;
+        Ok(FilesystemConfig { path: url.to_string(), backend: Box::new(IndexedBackend::new("", index)), options: FilesystemOptions::default(), own_options: false }
+            .with_options(options))
+    }
+    /// Constructs a configuration object that reads from a custom [`Backend`].
+    /// The path of this configuration is empty.
+    pub fn with_backend(backend: impl Backend + 'static) -> Self {
+        FilesystemConfig { path: String::new(), backend: Box::new(backend), options: FilesystemOptions::default(), own_options: false }
+    }
+    /// Replaces the [`FilesystemOptions`] of this configuration and returns it.
+    pub fn with_options(mut self, options: FilesystemOptions) -> Self {
+        self.set_options(options);
+        self
+    }
+    /// Replaces the [`FilesystemOptions`] of this configuration.
+    pub fn set_options(&mut self, options: FilesystemOptions) {
+        self.backend.set_options(&options);
+        self.options = options;
+        self.own_options = true;
+    }
+    /// Gives this configuration the options of the one that contains it,
+    /// unless it has options of its own.
+    pub(crate) fn inherit_options(&mut self, options: &FilesystemOptions) {
+        if !self.own_options {
+            self.backend.set_options(options);
+            self.options = options.clone();
+        }
+    }
+    /// Returns the [`FilesystemOptions`] of this configuration.
+    pub fn options(&self) -> &FilesystemOptions {
+        &self.options
     }
     
     /// Returns the type of Filesystem in this configuration
     pub fn fs_type(&self) -> FilesystemType {
-        match self.index {
-            Some(_) => FilesystemType::Indexed,
-            None => FilesystemType::Filesystem,
-        }
+        self.backend.fs_type()
+    }
+    /// Returns the [`Backend`] that this configuration reads from.
+    pub fn backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
     }
     /// Returns the path to the virtual filesystem.
     pub fn path(&self) -> String {
@@ -61,10 +135,13 @@ impl FilesystemConfig {
     /// This function returns [`None`] if the FS Type is not [`FilesystemType::Filesystem`] because
     /// otherwise the function would generate an invalid path.  
     /// The Filesystem Type can be checked with [`FilesystemConfig::fs_type`]
+    /// 
+    /// It also returns [`None`] if the path would escape the root (with `..` or symlinks).
     pub fn to_path(&self, path: &str) -> Option<String> {
         if let FilesystemType::Filesystem = self.fs_type() {
-            let c = self.path.clone();
-            Some(c + path)
+            let real_path = self.path.clone() + &virtual_path::normalize(path).ok()?;
+            let bound_checker = BoundChecker::new(Path::new(&self.path)).ok()?;
+            bound_checker.is_in_bounds(Path::new(&real_path)).ok()?.then_some(real_path)
         } else {
             None
         }
@@ -83,25 +160,20 @@ impl FilesystemConfig {
     /// of ORO Filesystem or translate local Asset Pack indices
     /// into or from AURA files.
     pub fn __get_raw_index_file(&self) -> Option<IndexFile> {
-        if let Some(asset_map) = &self.index {
-            let index_file: IndexFile = asset_map.into();
-            Some(index_file)
-        } else {
-            None
-        }
+        self.backend.asset_map().map(IndexFile::from)
     }
 
     /// Returns the index information for a file.   
     /// It simply returns a Value in the [`AssetMap`] for the
     /// Key passed as a parameter.
     /// 
-    /// This can fail if a filesystem is unindexed (like the native filesystem),
+    /// This can fail if a filesystem is unindexed (like the native filesystem or a custom [`Backend`]),
     /// or if the file is not found. Even if the filesystem is unindexed, this
     /// function won't panic.
     pub fn get_index_for_file(&self, path: &str) -> FilesystemResult<IndexType> {
-        match &self.index {
+        match self.backend.asset_map() {
             Some(asset_map) => {
-                match asset_map.get(path) {
+                match asset_map.get(&virtual_path::normalize(path)?) {
                     Some(index) => Ok(index.clone()),
                     None => Err(FilesystemError::NotFound(path.to_string())),
                 }
@@ -110,9 +182,14 @@ impl FilesystemConfig {
         }
     }
 
-    /// If a `*.oroi` file exists, the indices are read and returned,
+    /// If `*.oroi` files exist, the indices of all of them are read, merged and returned,
     /// if no indices file is found, an index configuration of [`None`] is returned.
     /// 
+    /// Index files are merged in alphabetical order, so the result is always the same
+    /// regardless of the order in which the OS lists them. Two index files can't
+    /// define the same path, this returns [`FilesystemError::DuplicatePathsInIndex`]
+    /// with the names of both index files if they do.
+    /// 
     /// An index configuration of [`Some`] indicates that the filesystem is Indexed (Aura or AssetPackage),
     /// an index configuration of [`None`] indicates that it is Unindexed (Native Filesystem)
     fn autodetect_filesystem(root: &str) -> FilesystemResult<Option<AssetMap>> {
@@ -121,6 +198,8 @@ impl FilesystemConfig {
             Ok(f) => f,
             Err(e) => return Err(FilesystemError::Generic(root.to_string(), e.to_string())),
         };
+
+        let mut index_files = Vec::new();
         for file in files {
             let entry = match file {
                 Ok(e) => e,
@@ -131,15 +210,45 @@ impl FilesystemConfig {
             
             if let Some(ext) = file_path.extension() {
                 if ext == "oroi" {
-                    let index_file = IndexFile::from_file(&file_path)?;
-                    let asset_map= AssetMap::try_from(index_file)?;
-
-                    return Ok(Some(asset_map))
+                    index_files.push(file_path);
                 }
             }
         }
+        if index_files.is_empty() {
+            return Ok(None);
+        }
+        index_files.sort();
+
+        // Index file that defines every path, to report duplicates
+        let mut sources: HashMap<String, String> = HashMap::new();
+        let mut asset_map = AssetMap::new();
+        for file_path in index_files {
+            let index_name = file_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let index_map = Self::load_index(&file_path)?;
+
+            // Sorted so the reported duplicate is always the same
+            let mut paths: Vec<String> = index_map.keys().cloned().collect();
+            paths.sort();
+            if let Some((duplicate, previous)) = paths.iter().find_map(|p| sources.get(p).map(|previous| (p, previous))) {
+                return Err(FilesystemError::DuplicatePathsInIndex(duplicate.clone(), vec![previous.clone(), index_name]));
+            }
+            for path in paths {
+                sources.insert(path, index_name.clone());
+            }
+            asset_map.extend(index_map);
+        }
         
-        return Ok(None);
+        Ok(Some(asset_map))
+    }
+
+    /// Reads an index file and converts it into an [`AssetMap`]
+    fn load_index(file_path: &Path) -> FilesystemResult<AssetMap> {
+        let index_name = file_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let index_file = IndexFile::from_file(file_path)?;
+        AssetMap::try_from(index_file).map_err(|e| match e {
+            FilesystemError::DuplicatePathsInIndex(path, _) => FilesystemError::DuplicatePathsInIndex(path, vec![index_name]),
+            e => e,
+        })
     }
 
     fn get_usable_root(root: &str) -> String {
@@ -161,7 +270,7 @@ impl FilesystemConfig {
 
 #[cfg(test)]
 mod tests {
-    use crate::{FilesystemConfig, FilesystemResult, FilesystemType};
+    use crate::{FilesystemConfig, FilesystemError, FilesystemResult, FilesystemType};
 
     #[test]
     fn check_assetpackage_detection() -> FilesystemResult<()> {
@@ -183,4 +292,57 @@ mod tests {
         assert_eq!(configuration.fs_type(), FilesystemType::Filesystem);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn keep_paths_in_bounds() -> FilesystemResult<()> {
+        let configuration = FilesystemConfig::with_root("tests/filesystem")?;
+        assert_eq!(configuration.to_path("testfile.txt"), Some(String::from("tests/filesystem/testfile.txt")));
+        assert_eq!(configuration.to_path("../aura/webmod.oroi"), None);
+        assert_eq!(configuration.to_path("invented/../../testfile.txt"), None);
+        assert_eq!(FilesystemConfig::with_root("tests/aura")?.to_path("webmod.oroi"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_multiple_indices() -> FilesystemResult<()> {
+        let configuration = FilesystemConfig::with_root("tests/multiple")?;
+        assert!(configuration.get_index_for_file("virtualFolder/vfile1.txt").is_ok());
+        assert!(configuration.get_index_for_file("virtualFolder/patch.txt").is_ok());
+        assert!(configuration.get_index_for_file("shared/readme.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn choose_index_by_name() -> FilesystemResult<()> {
+        let configuration = FilesystemConfig::with_index("tests/multiple", "patch.oroi")?;
+        assert_eq!(configuration.fs_type(), FilesystemType::Indexed);
+        assert!(configuration.get_index_for_file("virtualFolder/patch.txt").is_ok());
+        assert!(configuration.get_index_for_file("virtualFolder/vfile1.txt").is_err());
+
+        assert!(FilesystemConfig::with_index("tests/multiple", "invented.oroi").is_err());
+        assert!(matches!(FilesystemConfig::with_index("tests/multiple", "../conflict/a.oroi"), Err(FilesystemError::OutOfBounds(_, _))));
+        let absolute = std::fs::canonicalize("tests/conflict/a.oroi")?;
+        assert!(matches!(FilesystemConfig::with_index("tests/multiple", &absolute.to_string_lossy()), Err(FilesystemError::OutOfBounds(_, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_indices() {
+        match FilesystemConfig::with_root("tests/conflict") {
+            Err(FilesystemError::DuplicatePathsInIndex(path, sources)) => {
+                assert_eq!(path, "shared/readme.txt");
+                assert_eq!(sources, vec!["a.oroi".to_string(), "b.oroi".to_string()]);
+            },
+            other => panic!("Expected duplicate paths error, got {other:?}"),
+        }
+
+        // Index files are always loaded in the same order, so `duplicate_paths.oroi` fails first
+        match FilesystemConfig::with_root("tests/errors") {
+            Err(FilesystemError::DuplicatePathsInIndex(path, sources)) => {
+                assert_eq!(path, "virtualFolder/vfile1.txt");
+                assert_eq!(sources, vec!["duplicate_paths.oroi".to_string()]);
+            },
+            other => panic!("Expected duplicate paths error, got {other:?}"),
+        }
+    }
+}
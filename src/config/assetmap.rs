@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{config::index::{IndexFile, IndexType}, FilesystemError};
+use crate::{config::{index::{IndexFile, IndexType}, virtual_path}, FilesystemError};
 
 /// A [`HashMap`] containing all the paths and Aura/OAP data for every file in
 /// the Virtual Filesystem
@@ -10,8 +10,11 @@ impl TryFrom<IndexFile> for AssetMap {
     type Error = FilesystemError;
     
     /// Transforms an [`IndexFile`] into an [`AssetMap`].  
-    /// This operation can fail if the [`IndexFile`] contains duplicate file entries. In this case,
-    /// [`FilesystemError::DuplicatePathsInIndex`] will be returned
+    /// Every path is normalized with [`virtual_path::normalize`].
+    /// 
+    /// This operation can fail if the [`IndexFile`] contains duplicate file entries (after normalizing
+    /// them). In this case, [`FilesystemError::DuplicatePathsInIndex`] will be returned. It can also fail
+    /// with [`FilesystemError::OutOfBounds`] if a path contains `..`.
     /// 
     /// This function does not check for negative numbers, because negative indices or file
     /// sizes would already give an error while parsing the [`IndexFile`] from JSON (because
@@ -20,9 +23,10 @@ impl TryFrom<IndexFile> for AssetMap {
         let mut map = AssetMap::with_capacity(value.files.len());
 
         for file in value.files {
+            let path = virtual_path::normalize(&file.path)?;
             // If it's some, we already had this path registered
-            if map.insert(file.path.clone(), file.index).is_some() {
-                return Err(FilesystemError::DuplicatePathsInIndex(file.path));
+            if map.insert(path, file.index).is_some() {
+                return Err(FilesystemError::DuplicatePathsInIndex(file.path, Vec::new()));
             }
         }
 
@@ -34,7 +38,7 @@ impl TryFrom<IndexFile> for AssetMap {
 mod tests {
     use std::{path::PathBuf, str::FromStr};
 
-    use crate::{config::{assetmap::AssetMap, index::{AssetPackIndex, IndexEntry, IndexFile, IndexType}}, FilesystemResult};
+    use crate::{config::{assetmap::AssetMap, index::{AssetPackIndex, IndexEntry, IndexFile, IndexType}}, FilesystemError, FilesystemResult};
 
     /// Serializes an OAPI OROI file and checks its contents once converted to an [`AssetMap`] via [`TryFrom`]
     #[test]
@@ -112,6 +116,28 @@ mod tests {
         asset_map.unwrap_err();
     }
 
+    #[test]
+    fn from_index_file_normalized_paths() {
+        let index_file = IndexFile::from_str(r#"[
+            {"path": "./virtualFolder//vfile1.txt", "index": {"url": "https://example.com/vfile1.txt", "hash": null}},
+            {"path": "\\binaries\\example.bin", "index": {"url": "https://example.com/example.bin", "hash": null}}
+        ]"#).unwrap();
+        let asset_map: AssetMap = index_file.try_into().unwrap();
+        assert!(asset_map.contains_key("virtualFolder/vfile1.txt"));
+        assert!(asset_map.contains_key("binaries/example.bin"));
+
+        let duplicated = IndexFile::from_str(r#"[
+            {"path": "virtualFolder/vfile1.txt", "index": {"url": "https://example.com/vfile1.txt", "hash": null}},
+            {"path": "/virtualFolder/vfile1.txt", "index": {"url": "https://example.com/vfile1.txt", "hash": null}}
+        ]"#).unwrap();
+        assert!(matches!(AssetMap::try_from(duplicated), Err(FilesystemError::DuplicatePathsInIndex(_, _))));
+
+        let escaping = IndexFile::from_str(r#"[
+            {"path": "../vfile1.txt", "index": {"url": "https://example.com/vfile1.txt", "hash": null}}
+        ]"#).unwrap();
+        assert!(matches!(AssetMap::try_from(escaping), Err(FilesystemError::OutOfBounds(_, _))));
+    }
+
     #[test]
     fn from_index_file_negative_filesize() {
         IndexFile::from_file(&PathBuf::from_str("tests/errors/negative_filesize.oroi").unwrap()).unwrap_err();
@@ -1,5 +1,7 @@
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 
+#[cfg(feature = "aura")]
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
 use crate::{config::assetmap::AssetMap, readers::filesystem::read_to_string, FilesystemError, FilesystemResult};
@@ -14,6 +16,10 @@ pub enum IndexType {
 }
 
 /// Data necessary to read files from Obstruction Asset Packages
+/// 
+/// `package` is usually a path relative to the root of the filesystem,
+/// but it can also be an `http://` or `https://` URL. Files of remote
+/// packages are downloaded with HTTP Range requests.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AssetPackIndex {
     pub package: String,
@@ -21,11 +27,33 @@ pub struct AssetPackIndex {
     pub file_size: u64
 }
 
+impl AssetPackIndex {
+    /// Returns `true` if the package is a URL instead of a local file.
+    /// The scheme is case-insensitive, like in every other URL.
+    pub fn is_remote(&self) -> bool {
+        let scheme = self.package.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        matches!(scheme.as_deref(), Some("http" | "https"))
+    }
+}
+
 /// Data necessary to read files from web-based asset maps (Aura)
+/// 
+/// `mirrors` are other URLs that serve the same file. They're tried in order
+/// when `url` can't be read. If there's a `hash`, data from a mirror that
+/// doesn't match it is rejected and the next mirror is tried.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AuraIndex {
     pub url: String,
-    pub hash: Option<String>
+    pub hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>
+}
+
+impl AuraIndex {
+    /// Returns the URL and the mirrors, in order
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.mirrors.iter().map(String::as_str))
+    }
 }
 
 /// A file and its Aura/OAP data
@@ -56,10 +84,44 @@ impl IndexEntry {
 
 impl IndexFile {
     pub fn from_file(path: &Path) -> FilesystemResult<Self> {
-        let index_file_json = read_to_string(&path.as_os_str().to_string_lossy())?;
+        let index_file_json = read_to_string(path)?;
         Self::from_str(&index_file_json)
     }
-    pub fn from_str(contents: &str) -> FilesystemResult<Self> {
+
+    /// Resolves every relative Aura URL (and mirror) and Asset Package path of this index
+    /// against a base URL (usually the URL the index was downloaded from).
+    /// URLs that are already absolute are left as they are.
+    /// 
+    /// For example, with a base URL of `https://cdn.example.com/game/indices.oroi`,
+    /// `package.oap` becomes `https://cdn.example.com/game/package.oap`
+    /// and `/shared/logo.png` becomes `https://cdn.example.com/shared/logo.png`.
+    #[cfg(feature = "aura")]
+    pub fn resolve_urls(&mut self, base_url: &str) -> FilesystemResult<()> {
+        let base = Url::parse(base_url).map_err(|e| FilesystemError::FetchError(base_url.to_string(), e.to_string(), None))?;
+        let resolve = |path: &str, relative: &str| match base.join(relative) {
+            Ok(url) => Ok(url.to_string()),
+            Err(e) => Err(FilesystemError::Generic(path.to_string(), format!("Invalid URL \"{relative}\": {e}"))),
+        };
+
+        for entry in &mut self.files {
+            match &mut entry.index {
+                IndexType::AssetPack(asset_pack_index) => asset_pack_index.package = resolve(&entry.path, &asset_pack_index.package)?,
+                IndexType::Aura(aura_index) => {
+                    aura_index.url = resolve(&entry.path, &aura_index.url)?;
+                    for mirror in &mut aura_index.mirrors {
+                        *mirror = resolve(&entry.path, mirror)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for IndexFile {
+    type Err = FilesystemError;
+
+    fn from_str(contents: &str) -> FilesystemResult<Self> {
         let files = match serde_json::from_str(contents) {
             Ok(f) => f,
             Err(e) => return Err(FilesystemError::DeserializationError(e.to_string())),
@@ -105,12 +167,52 @@ impl From<&AssetMap> for IndexFile {
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use crate::config::index::{AssetPackIndex, AuraIndex, IndexEntry, IndexFile, IndexType};
     
     const EXPECTED_AURA: &str = r#"[{"path":"virtualFolder/vfile1.txt","index":{"url":"https://pastebin.com/raw/t0qjYDWt","hash":null}},{"path":"virtualFolder/vfile1-copy.txt","index":{"url":"https://pastebin.com/raw/t0qjYDWt","hash":null}}]"#;
     const EXPECTED_OAP: &str = r#"[{"path":"virtualFolder/vfile1.txt","index":{"package":"folder/example.oap","starting_index":0,"file_size":10}},{"path":"virtualFolder/vfile1-copy.txt","index":{"package":"folder/example.oap","starting_index":11,"file_size":10}}]"#;
     const EXPECTED_MIXED: &str = r#"[{"path":"virtualFolder/vfile-local.txt","index":{"package":"folder/example.oap","starting_index":0,"file_size":10}},{"path":"virtualFolder/vfile-networked.txt","index":{"url":"https://pastebin.com/raw/t0qjYDWt","hash":null}}]"#;
 
+    #[test]
+    fn remote_packages() {
+        let index = |package: &str| AssetPackIndex { package: package.to_string(), starting_index: 0, file_size: 10 };
+        assert!(index("https://cdn.example.com/game.oap").is_remote());
+        assert!(index("http://localhost:8080/game.oap").is_remote());
+        assert!(index("HTTPS://cdn.example.com/game.oap").is_remote());
+        assert!(!index("package.oap").is_remote());
+        assert!(!index("folder/https.oap").is_remote());
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn resolve_relative_urls() {
+        let mut index = IndexFile::from_str(r#"[
+            {"path":"relative.txt","index":{"url":"files/relative.txt","hash":null}},
+            {"path":"from_root.txt","index":{"url":"/from_root.txt","hash":null}},
+            {"path":"absolute.txt","index":{"url":"https://pastebin.com/raw/t0qjYDWt","hash":null,"mirrors":["mirror/absolute.txt","https://mirror.example.com/absolute.txt"]}},
+            {"path":"packaged.txt","index":{"package":"../package.oap","starting_index":0,"file_size":10}}
+        ]"#).unwrap();
+        index.resolve_urls("https://cdn.example.com/game/v1/indices.oroi").unwrap();
+
+        let urls: Vec<String> = index.files.iter().map(|entry| match &entry.index {
+            IndexType::AssetPack(asset_pack_index) => asset_pack_index.package.clone(),
+            IndexType::Aura(aura_index) => aura_index.url.clone(),
+        }).collect();
+        assert_eq!(urls, vec![
+            "https://cdn.example.com/game/v1/files/relative.txt",
+            "https://cdn.example.com/from_root.txt",
+            "https://pastebin.com/raw/t0qjYDWt",
+            "https://cdn.example.com/game/package.oap"
+        ]);
+        assert!(matches!(&index.files[2].index, IndexType::Aura(aura_index) if aura_index.mirrors == vec![
+            "https://cdn.example.com/game/v1/mirror/absolute.txt",
+            "https://mirror.example.com/absolute.txt"
+        ]));
+        assert!(index.resolve_urls("not a url").is_err());
+    }
+
     #[test]
     fn serialize_aura() {
         let index: IndexFile = IndexFile {
@@ -119,14 +221,16 @@ mod tests {
                     path: String::from("virtualFolder/vfile1.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 },
                 IndexEntry {
                     path: String::from("virtualFolder/vfile1-copy.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 }
             ] 
@@ -144,14 +248,16 @@ mod tests {
                     path: String::from("virtualFolder/vfile1.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 },
                 IndexEntry {
                     path: String::from("virtualFolder/vfile1-copy.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 }
             ]
@@ -241,7 +347,8 @@ mod tests {
                     path: String::from("virtualFolder/vfile-networked.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 }
             ]
@@ -267,7 +374,8 @@ mod tests {
                     path: String::from("virtualFolder/vfile-networked.txt"),
                     index: IndexType::Aura(AuraIndex {
                         url: String::from("https://pastebin.com/raw/t0qjYDWt"),
-                        hash: None
+                        hash: None,
+                        mirrors: Vec::new()
                     })
                 }
             ]
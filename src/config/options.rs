@@ -0,0 +1,358 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+#[cfg(feature = "aura")]
+use crate::readers::{scheme::{DataSchemeHandler, SchemeHandler}, transfer::{CancellationToken, ProgressObserver, Transfer}};
+
+/// Options of a [`crate::FilesystemConfig`] that change how files are read.
+/// 
+/// Options are applied with [`crate::FilesystemConfig::with_options`].
+#[derive(Debug, Clone)]
+pub struct FilesystemOptions {
+    cache_dir: Option<PathBuf>,
+    offline: bool,
+    revalidation: Revalidation,
+    max_concurrent_downloads: usize,
+    mirror_ranking: bool,
+    http: HttpOptions,
+    max_read_size: Option<u64>,
+    max_total_size: Option<u64>,
+    /// Bytes read so far, shared by every clone of these options
+    total_read: Arc<AtomicU64>,
+    #[cfg(feature = "aura")]
+    scheme_handlers: HashMap<String, Arc<dyn SchemeHandler>>,
+    #[cfg(feature = "aura")]
+    transfer: Transfer
+}
+
+impl Default for FilesystemOptions {
+    fn default() -> Self {
+        FilesystemOptions {
+            cache_dir: None,
+            offline: false,
+            revalidation: Revalidation::Never,
+            max_concurrent_downloads: 8,
+            mirror_ranking: false,
+            http: HttpOptions::default(),
+            max_read_size: None,
+            max_total_size: None,
+            total_read: Arc::default(),
+            #[cfg(feature = "aura")]
+            scheme_handlers: HashMap::from([(String::from("data"), Arc::new(DataSchemeHandler) as Arc<dyn SchemeHandler>)]),
+            #[cfg(feature = "aura")]
+            transfer: Transfer::default()
+        }
+    }
+}
+
+impl FilesystemOptions {
+    /// Constructs the default options: no cache, online mode and up to
+    /// 8 concurrent downloads when prefetching.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores every Aura file that is downloaded in a directory, so it is
+    /// only downloaded once. The directory is created if it doesn't exist.
+    /// 
+    /// Files are identified by their hash if the index has one, or by their URL
+    /// if it doesn't.
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// In offline mode Aura files are only read from the cache. Reading a file
+    /// that isn't cached fails with [`crate::FilesystemError::Offline`].
+    /// 
+    /// Files of remote Asset Packages are never cached, so they always fail
+    /// in offline mode.
+    pub fn with_offline_mode(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Sets when cached Aura files without a hash are checked for changes.
+    /// [`Revalidation::Never`] by default.
+    /// 
+    /// Files are checked with a conditional request that sends the `ETag` and
+    /// `Last-Modified` headers of the cached response. A `304 Not Modified`
+    /// response is a cache hit, anything else replaces the cached file.
+    pub fn with_revalidation(mut self, revalidation: Revalidation) -> Self {
+        self.revalidation = revalidation;
+        self
+    }
+
+    /// Maximum number of files that [`crate::prefetch`] downloads at the same time.
+    /// It's always at least 1.
+    pub fn with_max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
+        self.max_concurrent_downloads = max_concurrent_downloads.max(1);
+        self
+    }
+
+    /// Aura entries with mirrors are tried in the order of the index by default.
+    /// With mirror ranking, hosts that failed recently are tried after the
+    /// ones that didn't, so a host that is down doesn't slow down every read.
+    pub fn with_mirror_ranking(mut self, mirror_ranking: bool) -> Self {
+        self.mirror_ranking = mirror_ranking;
+        self
+    }
+
+    /// Sets the options of the HTTP client used to download Aura files
+    pub fn with_http(mut self, http: HttpOptions) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Maximum size of one Asset Package entry or Aura download, in bytes.
+    /// Reads of bigger files fail with [`crate::FilesystemError::SizeLimitExceeded`].
+    /// 
+    /// Sizes that are known in advance (Asset Package entries and downloads with
+    /// a `Content-Length` header) are checked before anything is allocated, and
+    /// downloads are stopped as soon as they go over the limit.
+    pub fn with_max_read_size(mut self, bytes: u64) -> Self {
+        self.max_read_size = Some(bytes);
+        self
+    }
+
+    /// Maximum number of bytes that can be read from Asset Packages and Aura URLs
+    /// with these options, adding every read. Reads that would go over it fail with
+    /// [`crate::FilesystemError::SizeLimitExceeded`].
+    /// 
+    /// The count starts at zero when this is called and is shared by every clone of
+    /// these options, so configurations that use the same options share the limit.
+    /// Give each configuration its own options to limit them separately, or call
+    /// [`FilesystemOptions::reset_total_read`] to start counting again. Reads that
+    /// fail don't count.
+    pub fn with_max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = Some(bytes);
+        self.total_read = Arc::default();
+        self
+    }
+
+    /// Reads Aura URLs with a specific scheme (like `file` or `steam-workshop`)
+    /// with a [`SchemeHandler`] instead of downloading them. Replaces the
+    /// previous handler of that scheme, if there was one.
+    /// 
+    /// Offline mode and the cache only apply to URLs that are downloaded with HTTP.
+    #[cfg(feature = "aura")]
+    pub fn with_scheme_handler(mut self, scheme: &str, handler: impl SchemeHandler + 'static) -> Self {
+        self.scheme_handlers.insert(scheme.to_ascii_lowercase(), Arc::new(handler));
+        self
+    }
+
+    /// Notifies an observer of the progress of every download of the configuration,
+    /// including the downloads of [`crate::prefetch`]
+    #[cfg(feature = "aura")]
+    pub fn with_progress_observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.transfer = self.transfer.with_progress(observer);
+        self
+    }
+
+    /// Stops every download of the configuration when the token is cancelled.
+    /// Downloads fail with [`crate::FilesystemError::Cancelled`].
+    #[cfg(feature = "aura")]
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.transfer = self.transfer.with_cancellation(token);
+        self
+    }
+
+    /// Returns the cache directory, if there is one
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Returns `true` if offline mode is enabled
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Returns when cached files are checked for changes
+    pub fn revalidation(&self) -> Revalidation {
+        self.revalidation
+    }
+
+    /// Returns the maximum number of concurrent downloads
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.max_concurrent_downloads
+    }
+
+    /// Returns `true` if mirrors are ranked by the health of their hosts
+    pub fn mirror_ranking(&self) -> bool {
+        self.mirror_ranking
+    }
+
+    /// Returns the options of the HTTP client
+    pub fn http(&self) -> &HttpOptions {
+        &self.http
+    }
+
+    /// Returns the maximum size of one read, if there is one
+    pub fn max_read_size(&self) -> Option<u64> {
+        self.max_read_size
+    }
+
+    /// Returns the maximum number of bytes of all reads, if there is one
+    pub fn max_total_size(&self) -> Option<u64> {
+        self.max_total_size
+    }
+
+    /// Returns the number of bytes read from Asset Packages and Aura URLs with
+    /// these options and their clones. The count starts again when
+    /// [`FilesystemOptions::with_max_total_size`] or
+    /// [`FilesystemOptions::reset_total_read`] is called.
+    pub fn total_read(&self) -> u64 {
+        self.total_read.load(Ordering::Relaxed)
+    }
+
+    /// Starts counting the bytes of [`FilesystemOptions::total_read`] again,
+    /// for these options and every clone of them.
+    pub fn reset_total_read(&self) {
+        self.total_read.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn total_counter(&self) -> Arc<AtomicU64> {
+        self.total_read.clone()
+    }
+
+    /// Returns the handler of a scheme, if there is one
+    #[cfg(feature = "aura")]
+    pub fn scheme_handler(&self, scheme: &str) -> Option<&dyn SchemeHandler> {
+        self.scheme_handlers.get(&scheme.to_ascii_lowercase()).map(|handler| handler.as_ref())
+    }
+
+    /// Returns the progress observers and cancellation tokens of every download
+    #[cfg(feature = "aura")]
+    pub fn transfer(&self) -> &Transfer {
+        &self.transfer
+    }
+}
+
+/// When cached Aura files are checked for changes.
+/// 
+/// Only files without a hash are checked: a file with a hash can't change
+/// without failing its verification. Offline mode never checks files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Revalidation {
+    /// Cached files are used until they're removed from the cache
+    #[default]
+    Never,
+    /// Every read checks the cached file with the server
+    Always,
+    /// Cached files are checked when they were downloaded or last checked
+    /// longer than this ago
+    After(Duration)
+}
+
+/// Options of the HTTP client used to download Aura files.
+/// 
+/// Every configuration builds one client from these options and
+/// reuses it (and its connections) for every request.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    retries: u32,
+    backoff: Duration,
+    user_agent: String,
+    headers: Vec<(String, String)>,
+    bearer_tokens: HashMap<String, String>
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            timeout: None,
+            connect_timeout: None,
+            retries: 0,
+            backoff: Duration::from_millis(500),
+            user_agent: concat!("oro-filesystem/", env!("CARGO_PKG_VERSION")).to_string(),
+            headers: Vec::new(),
+            bearer_tokens: HashMap::new()
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Constructs the default options: no timeouts, no retries and no extra headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum time that a whole request (including reading the body) can take
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum time that connecting to a server can take
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries failed requests up to `retries` times. Requests are retried when
+    /// the connection fails or times out and when the server responds with a
+    /// transient error (`429`, `500`, `502`, `503` or `504`).
+    /// 
+    /// The first retry waits `backoff`, and every retry after that waits twice
+    /// as long as the previous one.
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the `User-Agent` header. `oro-filesystem/<version>` by default.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Adds a header that is sent in every request
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends `Authorization: Bearer <token>` in every request to `host`
+    /// (for example `cdn.example.com`).
+    pub fn with_bearer_token(mut self, host: &str, token: &str) -> Self {
+        self.bearer_tokens.insert(host.to_ascii_lowercase(), token.to_string());
+        self
+    }
+
+    /// Returns the timeout of a whole request, if there is one
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns the connection timeout, if there is one
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    /// Returns how many times failed requests are retried
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Returns how long the first retry waits
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Returns the `User-Agent` header
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Returns the headers that are sent in every request
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the bearer token for a host, if there is one
+    pub fn bearer_token(&self, host: &str) -> Option<&str> {
+        self.bearer_tokens.get(&host.to_ascii_lowercase()).map(String::as_str)
+    }
+}
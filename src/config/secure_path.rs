@@ -6,12 +6,13 @@
 //! This module contains tools to check that a file is actually
 //! inside the specified "bounds"
 
-use std::path::{Path, PathBuf};
+use std::{io, path::{Component, Path, PathBuf}};
 
 use crate::{FilesystemError, FilesystemResult};
 
 /// A structure containing the Root of a Filesystem.
 /// The path specified is used to check any other path.
+#[derive(Debug, Clone)]
 pub struct BoundChecker {
     root: PathBuf
 }
@@ -32,20 +33,35 @@ impl BoundChecker {
                 return true;
             }
         }
-        return false;
+        false
     }
 
-    // TODO: Decide what to do with this function once secure_path is fully implemented
-    #[allow(dead_code)]
     /// Checks if a specific path is inside the directory of this Bound Checker.
-    /// This function returns [`Err`] if there's any error while reading the specified
-    /// `path`.
+    /// The path doesn't need to exist (see [`canonicalize_lenient`]).
+    /// 
+    /// This function returns [`Err`] if there's any error while reading the
+    /// part of the specified `path` that exists.
     pub fn is_in_bounds(&self, path: &Path) -> FilesystemResult<bool> {
-        let canonical_path = path.canonicalize().map_err(|e| FilesystemError::from(e).with_path(path.as_os_str().to_string_lossy().to_string()))?;
+        let canonical_path = canonicalize_lenient(path).map_err(|e| FilesystemError::from(e).with_path(path.as_os_str().to_string_lossy().to_string()))?;
 
         Ok(self.is_in_bounds_canonical(&canonical_path))
     }
 
+    /// Returns the canonical version of a path if it is inside the directory of this
+    /// Bound Checker, or [`FilesystemError::OutOfBounds`] if it isn't.
+    /// The path doesn't need to exist (see [`canonicalize_lenient`]).
+    pub fn ensure_in_bounds(&self, path: &Path) -> FilesystemResult<PathBuf> {
+        let canonical_path = canonicalize_lenient(path).map_err(|e| FilesystemError::from(e).with_path(path.as_os_str().to_string_lossy().to_string()))?;
+
+        if !self.is_in_bounds_canonical(&canonical_path) {
+            return Err(FilesystemError::OutOfBounds(
+                path.as_os_str().to_string_lossy().to_string(),
+                self.root.as_os_str().to_string_lossy().to_string()
+            ));
+        }
+        Ok(canonical_path)
+    }
+
     /// Converts the specified path into a relative path IF the path specified
     /// is inside the root directory of this [`BoundChecker`]
     pub fn get_relative_string(&self, path: &Path) -> FilesystemResult<String> {
@@ -64,11 +80,41 @@ impl BoundChecker {
     }
 }
 
+/// Canonicalizes a path that might not exist.
+/// 
+/// The longest part of the path that exists is canonicalized (resolving symlinks)
+/// and the rest of the components are applied to it without touching the
+/// filesystem: `.` is ignored and `..` removes the last component.
+pub fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+
+    for existing in (0..=components.len()).rev() {
+        let prefix: PathBuf = components[..existing].iter().collect();
+        let prefix = if prefix.as_os_str().is_empty() { PathBuf::from(".") } else { prefix };
+
+        let mut canonical = match prefix.canonicalize() {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && existing > 0 => continue,
+            Err(e) => return Err(e),
+        };
+        for component in &components[existing..] {
+            match component {
+                Component::ParentDir => { canonical.pop(); },
+                Component::Normal(name) => canonical.push(name),
+                _ => {}
+            }
+        }
+        return Ok(canonical);
+    }
+    // `existing == 0` always returns
+    unreachable!()
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
-    use crate::{config::secure_path::BoundChecker, FilesystemResult};
+    use crate::{config::secure_path::BoundChecker, FilesystemError, FilesystemResult};
 
     #[test]
     fn check_bounds() -> FilesystemResult<()> {
@@ -84,7 +130,7 @@ mod tests {
 
         assert!(bound_checker.is_in_bounds(file_in_bounds)?);
         assert!(!bound_checker.is_in_bounds(file_out_of_bounds)?);
-        assert!(bound_checker.is_in_bounds(file_invented).is_err()); // not found
+        assert!(bound_checker.is_in_bounds(file_invented)?); // doesn't exist, but it would be in bounds
 
         // Check relative paths
         let relative_in_bounds = bound_checker.get_relative_string(file_in_bounds)?;
@@ -92,4 +138,20 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn check_bounds_of_invented_paths() -> FilesystemResult<()> {
+        let bound_checker = BoundChecker::new(Path::new("tests"))?;
+
+        assert!(bound_checker.is_in_bounds(Path::new("tests/invented/directory/file.txt"))?);
+        assert!(bound_checker.is_in_bounds(Path::new("tests/invented/../filesystem/testfile.txt"))?);
+        assert!(!bound_checker.is_in_bounds(Path::new("tests/invented/../../../file.txt"))?);
+        assert!(!bound_checker.is_in_bounds(Path::new("tests/../invented.txt"))?);
+
+        assert!(matches!(
+            bound_checker.ensure_in_bounds(Path::new("tests/../../etc/passwd")),
+            Err(FilesystemError::OutOfBounds(_, _))
+        ));
+        Ok(())
+    }
+}
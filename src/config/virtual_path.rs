@@ -0,0 +1,57 @@
+//! Paths in a Virtual Filesystem can be written in many ways that should
+//! all point to the same file: `virtualFolder/vfile1.txt`,
+//! `./virtualFolder/vfile1.txt`, `/virtualFolder//vfile1.txt` or
+//! `virtualFolder\vfile1.txt`.
+//! 
+//! Every path is normalized before it is looked up, and every path in an
+//! index is normalized before it is added to an [`crate::config::assetmap::AssetMap`],
+//! so all of them behave the same in every type of filesystem.
+
+use crate::{FilesystemError, FilesystemResult};
+
+/// Normalizes a virtual path:
+/// 
+/// - `\` is treated as a separator, just like `/`
+/// - Leading, trailing and duplicate separators are removed
+/// - `.` components are removed
+/// - `..` components are rejected with [`FilesystemError::OutOfBounds`]
+/// 
+/// The root of the Virtual Filesystem is an empty string.
+pub fn normalize(path: &str) -> FilesystemResult<String> {
+    let mut components = Vec::new();
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => continue,
+            ".." => return Err(FilesystemError::OutOfBounds(path.to_string(), String::from("/"))),
+            component => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::virtual_path::normalize, FilesystemError, FilesystemResult};
+
+    #[test]
+    fn normalize_equivalent_paths() -> FilesystemResult<()> {
+        let expected = "virtualFolder/vfile1.txt";
+        assert_eq!(normalize("virtualFolder/vfile1.txt")?, expected);
+        assert_eq!(normalize("./virtualFolder/vfile1.txt")?, expected);
+        assert_eq!(normalize("/virtualFolder//vfile1.txt")?, expected);
+        assert_eq!(normalize("virtualFolder\\vfile1.txt")?, expected);
+        assert_eq!(normalize("virtualFolder/./vfile1.txt/")?, expected);
+
+        assert_eq!(normalize("")?, "");
+        assert_eq!(normalize("/")?, "");
+        assert_eq!(normalize("./")?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn reject_parent_directories() {
+        assert!(matches!(normalize("../vfile1.txt"), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(normalize("virtualFolder/../vfile1.txt"), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(normalize("virtualFolder\\..\\..\\vfile1.txt"), Err(FilesystemError::OutOfBounds(_, _))));
+    }
+}
@@ -3,4 +3,5 @@
 //! 
 //! This allows you to extend the functionality of it.
 
-pub use crate::config::index;
\ No newline at end of file
+pub use crate::config::{assetmap, index, virtual_path};
+pub use crate::readers::{filesystem::NativeBackend, indexed::IndexedBackend, integrity};
\ No newline at end of file
@@ -7,73 +7,153 @@
 //! - Packed Obstruction Asset Packages
 //! - Aura files (Web-based file maps)
 
+mod backend;
 mod config;
 mod readers;
 mod packager;
+mod overlay;
+mod mount;
 
 pub mod extra;
 
+#[cfg(all(test, feature = "aura"))]
+mod test_server;
+
 // Config re-exports
-pub use config::{FilesystemConfig, FilesystemType};
+pub use config::{FilesystemConfig, FilesystemType, options::{FilesystemOptions, HttpOptions, Revalidation}};
 pub use readers::{FilesystemError, FilesystemResult};
 
+// Scheme handler re-exports
+#[cfg(feature = "aura")]
+pub use readers::scheme::{DataSchemeHandler, FileSchemeHandler, SchemeHandler};
+
+// Transfer re-exports
+#[cfg(feature = "aura")]
+pub use readers::transfer::{CancellationToken, ProgressObserver, Transfer};
+
+// Backend re-exports
+pub use backend::{Backend, BackendKind, DirEntry, Metadata, PrefetchEntry, PrefetchReport, ReadSeek};
+#[cfg(feature = "async")]
+pub use backend::BoxFuture;
+
+// Overlay re-exports
+pub use overlay::{Overlay, OverlayLayer};
+
+// Mount re-exports
+pub use mount::MountTable;
+
 // Packager re-export
 pub use packager::pack;
+#[cfg(feature = "aura")]
+pub use packager::pack_aura;
 
 /// Reads the file in the indicated path and
 /// returns its contents as a string
 pub fn read_to_string(path: &str, config: &FilesystemConfig) -> FilesystemResult<String> {
-    // Read files
-    match config.fs_type() {
-        FilesystemType::Filesystem => {
-            // println!("Reading file at v-filesystem path {} (real fs {})", path, config.to_path(path));
-            readers::filesystem::read_to_string(&config.to_path(path).unwrap())
-        }
-        FilesystemType::Indexed => {
-            // println!("Reading file at v-filesystem path {} (from indexed fs {})", path, config.path());
-            // Does this path have an index?
-            match config.get_index_for_file(path) {
-                Ok(index) => {
-                    // Is this index an AssetPackage or an Aura file?
-                    match index {
-                        config::index::IndexType::AssetPack(asset_pack_index) => readers::assetpackage::read_to_string(path, &config.path(), &asset_pack_index),
-                        config::index::IndexType::Aura(aura_index) => readers::aura::read_to_string(&aura_index.url),
-                    }
-                },
-                Err(e) => Err(e),
-            }
-        }
-    }
+    config.backend().read_to_string(path)
 }
 
 /// Reads the file in the indicated path and
 /// returns its contents as binary
 pub fn read(path: &str, config: &FilesystemConfig) -> FilesystemResult<Vec<u8>> {
-    // Read files
-    match config.fs_type() {
-        FilesystemType::Filesystem => {
-            readers::filesystem::read(&config.to_path(path).unwrap())
-        }
-        FilesystemType::Indexed => {
-            // Does this path have an index?
-            match config.get_index_for_file(path) {
-                Ok(index) => {
-                    // Is this index an AssetPackage or an Aura file?
-                    match index {
-                        config::index::IndexType::AssetPack(asset_pack_index) => readers::assetpackage::read(path, &config.path(), &asset_pack_index),
-                        config::index::IndexType::Aura(aura_index) => readers::aura::read(&aura_index.url),
-                    }
-                },
-                Err(e) => Err(e),
-            }
-        }
-    }
+    config.backend().read(path)
+}
+
+/// Reads the file in the indicated path and returns its contents as a string.
+/// 
+/// If the file is downloaded, the download is reported to the progress
+/// observers of `transfer` and stopped by its cancellation tokens, besides
+/// the ones in the [`FilesystemOptions`] of the configuration.
+#[cfg(feature = "aura")]
+pub fn read_to_string_with(path: &str, config: &FilesystemConfig, transfer: &Transfer) -> FilesystemResult<String> {
+    config.backend().read_to_string_with(path, transfer)
+}
+
+/// Reads the file in the indicated path and returns its contents as binary.
+/// 
+/// If the file is downloaded, the download is reported to the progress
+/// observers of `transfer` and stopped by its cancellation tokens, besides
+/// the ones in the [`FilesystemOptions`] of the configuration. A cancelled
+/// download fails with [`FilesystemError::Cancelled`].
+#[cfg(feature = "aura")]
+pub fn read_with(path: &str, config: &FilesystemConfig, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+    config.backend().read_with(path, transfer)
+}
+
+/// Asynchronous version of [`read_to_string`].
+/// 
+/// Native and Asset Package files are read with [`tokio::fs`] and
+/// Aura files are downloaded with a non-blocking HTTP client.
+#[cfg(feature = "async")]
+pub async fn read_to_string_async(path: &str, config: &FilesystemConfig) -> FilesystemResult<String> {
+    config.backend().read_to_string_async(path).await
 }
 
+/// Asynchronous version of [`read`].
+/// 
+/// Native and Asset Package files are read with [`tokio::fs`] and
+/// Aura files are downloaded with a non-blocking HTTP client.
+#[cfg(feature = "async")]
+pub async fn read_async(path: &str, config: &FilesystemConfig) -> FilesystemResult<Vec<u8>> {
+    config.backend().read_async(path).await
+}
+
+/// Opens the file in the indicated path for reading without loading
+/// it into memory.
+/// 
+/// - Native files are opened as a [`std::fs::File`].
+/// - Asset Package files can only read and seek between the first and last
+///   byte of that file in the package.
+/// - Aura files are read while they're being downloaded. Seeking sends a new
+///   request with a `Range` header.
+pub fn open(path: &str, config: &FilesystemConfig) -> FilesystemResult<Box<dyn ReadSeek>> {
+    config.backend().open(path)
+}
+
+/// Returns the [`Metadata`] of the file or directory in the indicated path:
+/// its size, the kind of source that serves it and, depending on the
+/// source, its package and offset or its URL and hash.
+/// 
+/// This never reads the file. The size of Aura files is unknown until
+/// they are downloaded, so it's [`None`] for them.
+pub fn metadata(path: &str, config: &FilesystemConfig) -> FilesystemResult<Metadata> {
+    config.backend().metadata(path)
+}
+
+/// Lists the contents of the directory in the indicated path.
+/// An empty path lists the root of the virtual filesystem.
+/// 
+/// In indexed filesystems directories are virtual, they are derived
+/// from the paths of the indexed files.
+pub fn read_dir(path: &str, config: &FilesystemConfig) -> FilesystemResult<Vec<DirEntry>> {
+    config.backend().read_dir(path)
+}
+
+/// Lists the contents of the directory in the indicated path
+/// and all of its subdirectories, recursively.
+pub fn walk_dir(path: &str, config: &FilesystemConfig) -> FilesystemResult<Vec<DirEntry>> {
+    config.backend().walk_dir(path)
+}
+
+/// Downloads many files ahead of time, so reading them later doesn't
+/// wait for the network. Returns what happened with every path.
+/// 
+/// Aura files are downloaded concurrently, up to
+/// [`FilesystemOptions::max_concurrent_downloads`] at the same time. They are
+/// stored in the cache directory of the configuration, or in memory if it
+/// doesn't have one. Files in memory are dropped when they're read for the
+/// first time. Native and Asset Package files are only checked to exist, and
+/// files of remote Asset Packages (which are never cached) fail.
+pub fn prefetch<P: AsRef<str>>(paths: &[P], config: &FilesystemConfig) -> PrefetchReport {
+    let paths: Vec<&str> = paths.iter().map(AsRef::as_ref).collect();
+    config.backend().prefetch(&paths)
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::{read_to_string, read, FilesystemConfig, FilesystemResult};
+    use std::io::{Read, Seek, SeekFrom};
+
+    use crate::{metadata, open, read_to_string, read, read_dir, walk_dir, BackendKind, FilesystemConfig, FilesystemError, FilesystemResult};
 
     /// not much to test here
     #[test]
@@ -101,9 +181,11 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "aura")]
     #[test]
     fn read_from_aura() -> FilesystemResult<()> {
-        let config = FilesystemConfig::with_root("tests/aura")?;
+        let server = crate::test_server::TestServer::start();
+        let config = FilesystemConfig::from_index_url(&server.url("/webmod.oroi"))?;
         let contents_f1 = read_to_string("virtualFolder/vfile1.txt", &config)?;
         let contents_f2 = read_to_string("virtualFolder/vfile1-copy.txt", &config)?;
 
@@ -116,4 +198,421 @@ mod tests {
         assert_eq!(contents_bin, expected_bin);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn list_asset_pack() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        let root: Vec<String> = read_dir("", &config)?.into_iter().map(|entry| entry.name).collect();
+        assert_eq!(root, vec!["binaries", "otherFolder", "virtualFolder"]);
+
+        let all: Vec<(String, bool)> = walk_dir("", &config)?.into_iter().map(|entry| (entry.path, entry.is_dir)).collect();
+        assert_eq!(all, vec![
+            ("binaries".to_string(), true),
+            ("binaries/example.bin".to_string(), false),
+            ("otherFolder".to_string(), true),
+            ("otherFolder/someScript.lua".to_string(), false),
+            ("virtualFolder".to_string(), true),
+            ("virtualFolder/vfile1-copy.txt".to_string(), false),
+            ("virtualFolder/vfile1.txt".to_string(), false),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn list_real_filesystem() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/overlay")?;
+        let all: Vec<String> = walk_dir("", &config)?.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(all, vec!["virtualFolder", "virtualFolder/modded.txt", "virtualFolder/vfile1.txt"]);
+
+        let folder: Vec<String> = read_dir("virtualFolder/", &config)?.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(folder, vec!["virtualFolder/modded.txt", "virtualFolder/vfile1.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn stat_files() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/aura")?;
+        let aura_metadata = metadata("virtualFolder/vfile1.txt", &config)?;
+        assert_eq!(aura_metadata.kind, BackendKind::Aura);
+        assert_eq!(aura_metadata.url, Some("https://pastebin.com/raw/t0qjYDWt".to_string()));
+        assert_eq!(aura_metadata.hash, None);
+        assert_eq!(aura_metadata.size, None);
+
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        let oap_metadata = metadata("otherFolder/someScript.lua", &config)?;
+        assert_eq!(oap_metadata.kind, BackendKind::AssetPack);
+        assert_eq!(oap_metadata.size, Some(57));
+        assert_eq!(oap_metadata.package, Some("package.oap".to_string()));
+        assert_eq!(oap_metadata.offset, Some(56));
+
+        let config = FilesystemConfig::with_root("tests")?;
+        let native_metadata = metadata("filesystem/testfile.txt", &config)?;
+        assert_eq!(native_metadata.kind, BackendKind::Native);
+        assert_eq!(native_metadata.size, Some(13));
+        assert!(!native_metadata.is_dir);
+        assert!(metadata("filesystem", &config)?.is_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn open_streams() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        let mut file = open("otherFolder/someScript.lua", &config)?;
+        file.seek(SeekFrom::Start(9))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        assert_eq!(contents, "imposter is sus!! This is a script or something.");
+
+        let config = FilesystemConfig::with_root("tests")?;
+        let mut file = open("filesystem/testfile.txt", &config)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hello, World!");
+        Ok(())
+    }
+
+    #[test]
+    fn read_normalized_paths() -> FilesystemResult<()> {
+        for root in ["tests/assetpackage", "tests/overlay"] {
+            let config = FilesystemConfig::with_root(root)?;
+            let expected = read("virtualFolder/vfile1.txt", &config)?;
+            assert_eq!(read("./virtualFolder/vfile1.txt", &config)?, expected);
+            assert_eq!(read("/virtualFolder//vfile1.txt", &config)?, expected);
+            assert_eq!(read("virtualFolder\\vfile1.txt", &config)?, expected);
+            assert!(read("virtualFolder/../virtualFolder/vfile1.txt", &config).is_err());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "aura"))]
+    #[test]
+    fn read_from_aura_without_feature() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_root("tests/aura")?;
+        assert_eq!(metadata("virtualFolder/vfile1.txt", &config)?.kind, BackendKind::Aura);
+        assert!(matches!(read("virtualFolder/vfile1.txt", &config), Err(FilesystemError::FeatureDisabled(_, _))));
+        assert!(matches!(read_to_string("virtualFolder/vfile1.txt", &config), Err(FilesystemError::FeatureDisabled(_, _))));
+        assert!(matches!(open("binaries/example.bin", &config), Err(FilesystemError::FeatureDisabled(_, _))));
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn read_from_aura_offline() -> FilesystemResult<()> {
+        use crate::FilesystemOptions;
+
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("empty-cache");
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let config = FilesystemConfig::with_root("tests/aura")?
+            .with_options(FilesystemOptions::new().with_cache_dir(&cache_dir).with_offline_mode(true));
+
+        assert!(config.options().is_offline());
+        assert!(matches!(read("virtualFolder/vfile1.txt", &config), Err(FilesystemError::Offline(_))));
+        assert!(matches!(open("binaries/example.bin", &config), Err(FilesystemError::Offline(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn read_remote_package_offline() -> FilesystemResult<()> {
+        use crate::{
+            config::{assetmap::AssetMap, index::{AssetPackIndex, IndexType}},
+            extra::IndexedBackend,
+            test_server, FilesystemOptions
+        };
+
+        let server = test_server::TestServer::start();
+        let mut index = AssetMap::new();
+        index.insert("packed.txt".to_string(), IndexType::AssetPack(AssetPackIndex { package: server.url("/package.oap"), starting_index: 28, file_size: 28 }));
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index))
+            .with_options(FilesystemOptions::new().with_offline_mode(true));
+
+        assert!(matches!(read("packed.txt", &config), Err(FilesystemError::Offline(_))));
+        assert!(matches!(read_to_string("packed.txt", &config), Err(FilesystemError::Offline(_))));
+        assert!(matches!(open("packed.txt", &config), Err(FilesystemError::Offline(_))));
+        assert_eq!(server.hits("/package.oap"), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn prefetch_report() -> FilesystemResult<()> {
+        use crate::{prefetch, FilesystemOptions, MountTable};
+
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("prefetch-cache");
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let aura = FilesystemConfig::with_root("tests/aura")?
+            .with_options(FilesystemOptions::new().with_cache_dir(&cache_dir).with_offline_mode(true).with_max_concurrent_downloads(2));
+        let config = FilesystemConfig::with_backend(MountTable::new()
+            .with_mount("/core/", FilesystemConfig::with_root("tests/assetpackage")?)?
+            .with_mount("/remote/", aura)?);
+
+        let report = prefetch(&[
+            "/remote/virtualFolder/vfile1.txt",
+            "/core/virtualFolder/vfile1.txt",
+            "/remote/binaries/example.bin",
+            "/core/invented.txt",
+            "/unmounted.txt"
+        ], &config);
+
+        assert!(!report.is_success());
+        assert_eq!(report.succeeded().collect::<Vec<_>>(), vec!["/core/virtualFolder/vfile1.txt"]);
+        let failed: Vec<(&str, &FilesystemError)> = report.failed().collect();
+        assert!(matches!(failed[..], [
+            ("/remote/virtualFolder/vfile1.txt", FilesystemError::Offline(_)),
+            ("/remote/binaries/example.bin", FilesystemError::Offline(_)),
+            ("/core/invented.txt", FilesystemError::NotFound(_)),
+            ("/unmounted.txt", FilesystemError::NotMounted(_))
+        ]));
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn download_progress_and_cancellation() -> FilesystemResult<()> {
+        use std::sync::{Arc, Mutex};
+
+        use crate::{
+            config::{assetmap::AssetMap, index::{AssetPackIndex, AuraIndex, IndexType}},
+            extra::IndexedBackend,
+            read_to_string_with, read_with, test_server, CancellationToken, FilesystemOptions, Transfer
+        };
+
+        let server = test_server::TestServer::start();
+        let mut index = AssetMap::new();
+        index.insert("large.bin".to_string(), IndexType::Aura(AuraIndex { url: server.url("/large.bin"), hash: None, mirrors: Vec::new() }));
+        index.insert("text.txt".to_string(), IndexType::Aura(AuraIndex { url: server.url("/raw/t0qjYDWt"), hash: None, mirrors: Vec::new() }));
+        index.insert("packed.txt".to_string(), IndexType::AssetPack(AssetPackIndex { package: server.url("/package.oap"), starting_index: 28, file_size: 28 }));
+
+        // Per call
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index.clone()));
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let observed = progress.clone();
+        let transfer = Transfer::new().with_progress(move |_: &str, received, total| observed.lock().unwrap().push((received, total)));
+        assert_eq!(read_with("large.bin", &config, &transfer)?.len(), test_server::LARGE_SIZE);
+        let large_size = test_server::LARGE_SIZE as u64;
+        assert!(progress.lock().unwrap().windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(progress.lock().unwrap().last(), Some(&(large_size, Some(large_size))));
+
+        progress.lock().unwrap().clear();
+        assert_eq!(read_to_string_with("packed.txt", &config, &transfer)?, "hello, world! This is a test");
+        assert_eq!(progress.lock().unwrap().last(), Some(&(28, Some(28))));
+
+        // Cancelled before the download starts, nothing is requested
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(read_with("text.txt", &config, &Transfer::new().with_cancellation(token)), Err(FilesystemError::Cancelled(_))));
+        assert_eq!(server.hits("/raw/t0qjYDWt"), 0);
+
+        // Cancelled halfway
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let halfway = Transfer::new()
+            .with_cancellation(token)
+            .with_progress(move |_: &str, received, _| if received > 1024 * 1024 { canceller.cancel() });
+        assert!(matches!(read_with("large.bin", &config, &halfway), Err(FilesystemError::Cancelled(_))));
+
+        // On the configuration
+        let token = CancellationToken::new();
+        let downloaded: Arc<Mutex<Vec<String>>> = Arc::default();
+        let observed = downloaded.clone();
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index)).with_options(FilesystemOptions::new()
+            .with_progress_observer(move |url: &str, _, _| observed.lock().unwrap().push(url.to_string()))
+            .with_cancellation_token(token.clone()));
+        assert_eq!(read_to_string("text.txt", &config)?, test_server::TEXT);
+        assert_eq!(downloaded.lock().unwrap().last(), Some(&server.url("/raw/t0qjYDWt")));
+        token.cancel();
+        assert!(matches!(read("text.txt", &config), Err(FilesystemError::Cancelled(_))));
+        assert!(matches!(open("large.bin", &config), Err(FilesystemError::Cancelled(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn size_limits() -> FilesystemResult<()> {
+        use crate::FilesystemOptions;
+
+        let config = FilesystemConfig::with_root("tests/assetpackage")?
+            .with_options(FilesystemOptions::new().with_max_read_size(20).with_max_total_size(30));
+        assert!(matches!(read("virtualFolder/vfile1-copy.txt", &config), Err(FilesystemError::SizeLimitExceeded(_, 20))));
+        assert_eq!(read("binaries/example.bin", &config)?, b"Hello, World!");
+        assert_eq!(read_to_string("binaries/example.bin", &config)?, "Hello, World!");
+        assert!(matches!(read("binaries/example.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 30))));
+        assert_eq!(config.options().total_read(), 26);
+
+        // Streams are counted when they're opened
+        config.options().reset_total_read();
+        open("binaries/example.bin", &config)?;
+        assert_eq!(config.options().total_read(), 13);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn size_limits_of_downloads() -> FilesystemResult<()> {
+        use crate::{
+            config::{assetmap::AssetMap, index::{AssetPackIndex, AuraIndex, IndexType}},
+            extra::IndexedBackend,
+            test_server, FilesystemOptions
+        };
+
+        let server = test_server::TestServer::start();
+        let mut index = AssetMap::new();
+        index.insert("large.bin".to_string(), IndexType::Aura(AuraIndex { url: server.url("/large.bin"), hash: None, mirrors: Vec::new() }));
+        index.insert("unsized.bin".to_string(), IndexType::Aura(AuraIndex { url: server.url("/unsized/large.bin"), hash: None, mirrors: Vec::new() }));
+        index.insert("text.txt".to_string(), IndexType::Aura(AuraIndex { url: server.url("/raw/t0qjYDWt"), hash: None, mirrors: Vec::new() }));
+        index.insert("tampered.txt".to_string(), IndexType::Aura(AuraIndex {
+            url: server.url("/raw/t0qjYDWt"),
+            hash: Some("sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f".to_string()),
+            mirrors: Vec::new()
+        }));
+        index.insert("huge.bin".to_string(), IndexType::AssetPack(AssetPackIndex { package: server.url("/package.oap"), starting_index: 0, file_size: u64::MAX }));
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index))
+            .with_options(FilesystemOptions::new().with_max_read_size(1024 * 1024));
+
+        // Known sizes fail before anything is downloaded
+        assert!(matches!(read("huge.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 1048576))));
+        assert_eq!(server.hits("/package.oap"), 0);
+        assert!(matches!(read("large.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 1048576))));
+
+        // Unknown sizes fail while they're downloaded
+        assert!(matches!(read("unsized.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 1048576))));
+        assert!(open("unsized.bin", &config)?.read_to_end(&mut Vec::new()).is_err());
+
+        // Failed downloads aren't counted in the total
+        config.options().reset_total_read();
+        assert!(matches!(read("unsized.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 1048576))));
+        assert!(matches!(read("tampered.txt", &config), Err(FilesystemError::HashMismatch(..))));
+        assert_eq!(config.options().total_read(), 0);
+
+        assert_eq!(read_to_string("text.txt", &config)?, test_server::TEXT);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn read_from_mirrors() -> FilesystemResult<()> {
+        use crate::{
+            config::{assetmap::AssetMap, index::{AuraIndex, IndexType}},
+            extra::IndexedBackend,
+            test_server, FilesystemOptions
+        };
+
+        let down = test_server::TestServer::start();
+        let mirror = test_server::TestServer::start();
+        let binary_hash = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+        let mut index = AssetMap::new();
+        index.insert("down.bin".to_string(), IndexType::Aura(AuraIndex {
+            url: down.url("/status/503"),
+            hash: None,
+            mirrors: vec![mirror.url("/raw/eQe9aqfZ")]
+        }));
+        // The first URL serves other data, only the mirror matches the hash
+        index.insert("tampered.bin".to_string(), IndexType::Aura(AuraIndex {
+            url: down.url("/raw/t0qjYDWt"),
+            hash: Some(binary_hash.to_string()),
+            mirrors: vec![mirror.url("/raw/eQe9aqfZ")]
+        }));
+        index.insert("missing.bin".to_string(), IndexType::Aura(AuraIndex {
+            url: down.url("/status/503"),
+            hash: None,
+            mirrors: vec![mirror.url("/status/404")]
+        }));
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index.clone()));
+
+        assert_eq!(read("down.bin", &config)?, test_server::BINARY);
+        assert_eq!(read("tampered.bin", &config)?, test_server::BINARY);
+        let mut contents = Vec::new();
+        open("down.bin", &config)?.read_to_end(&mut contents)?;
+        assert_eq!(contents, test_server::BINARY);
+        // Streams are verified at the end, a mismatch doesn't switch to the mirror
+        let error = open("tampered.bin", &config)?.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(error.get_ref().is_some_and(|inner| matches!(inner.downcast_ref(), Some(FilesystemError::HashMismatch(..)))));
+        // The error of the last mirror
+        assert!(matches!(read("missing.bin", &config), Err(FilesystemError::NotFound(_))));
+
+        // Hosts that failed are tried last
+        let ranked = FilesystemConfig::with_backend(IndexedBackend::new("", index))
+            .with_options(FilesystemOptions::new().with_mirror_ranking(true));
+        let failures = down.hits("/status/503");
+        assert_eq!(read("down.bin", &ranked)?, test_server::BINARY);
+        assert_eq!(down.hits("/status/503"), failures + 1);
+        assert_eq!(read("down.bin", &ranked)?, test_server::BINARY);
+        assert_eq!(down.hits("/status/503"), failures + 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn prefetch_into_memory() -> FilesystemResult<()> {
+        use crate::{prefetch, test_server};
+
+        let server = test_server::TestServer::start();
+        let config = FilesystemConfig::from_index_url(&server.url("/webmod.oroi"))?;
+
+        let report = prefetch(&["virtualFolder/vfile1.txt", "virtualFolder/vfile1-copy.txt", "binaries/example.bin"], &config);
+        assert!(report.is_success());
+        let downloads = server.hits("/raw/t0qjYDWt") + server.hits("/raw/eQe9aqfZ");
+
+        // Prefetched files are read from memory
+        assert_eq!(read_to_string("virtualFolder/vfile1.txt", &config)?, test_server::TEXT);
+        assert_eq!(read("binaries/example.bin", &config)?, test_server::BINARY);
+        assert_eq!(server.hits("/raw/t0qjYDWt") + server.hits("/raw/eQe9aqfZ"), downloads);
+
+        // And dropped once they're read
+        assert_eq!(read("binaries/example.bin", &config)?, test_server::BINARY);
+        assert_eq!(server.hits("/raw/t0qjYDWt") + server.hits("/raw/eQe9aqfZ"), downloads + 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn prefetch_asset_packages() -> FilesystemResult<()> {
+        use crate::{
+            config::{assetmap::AssetMap, index::{AssetPackIndex, IndexType}},
+            extra::IndexedBackend,
+            prefetch, test_server
+        };
+
+        let server = test_server::TestServer::start();
+        let entry = |package: String| IndexType::AssetPack(AssetPackIndex { package, starting_index: 0, file_size: 13 });
+        let mut index = AssetMap::new();
+        index.insert("local.txt".to_string(), entry("package.oap".to_string()));
+        index.insert("missing.txt".to_string(), entry("missing.oap".to_string()));
+        index.insert("remote.txt".to_string(), entry(server.url("/package.oap")));
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("tests/assetpackage", index));
+
+        // Local packages are checked, remote ones are never downloaded
+        let report = prefetch(&["local.txt", "missing.txt", "remote.txt"], &config);
+        let failed: Vec<(&str, &FilesystemError)> = report.failed().collect();
+        assert_eq!(report.succeeded().collect::<Vec<_>>(), vec!["local.txt"]);
+        assert!(matches!(failed[..], [("missing.txt", FilesystemError::NotFound(_)), ("remote.txt", FilesystemError::Generic(..))]));
+        assert_eq!(server.hits("/package.oap"), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn read_without_blocking() -> FilesystemResult<()> {
+        use crate::{read_async, read_to_string_async, FilesystemConfig, MountTable, Overlay};
+
+        let config = FilesystemConfig::with_root("tests/assetpackage")?;
+        assert_eq!(read_to_string_async("virtualFolder/vfile1.txt", &config).await?, "hello, world! This is a test");
+        assert_eq!(read_async("binaries/example.bin", &config).await?, b"Hello, World!");
+
+        let config = FilesystemConfig::with_root("tests")?;
+        assert_eq!(read_to_string_async("filesystem/testfile.txt", &config).await?, "Hello, World!");
+        assert!(matches!(read_async("../README.md", &config).await, Err(FilesystemError::OutOfBounds(_, _))));
+
+        let mounts = MountTable::new().with_mount("/core/", FilesystemConfig::with_root("tests/assetpackage")?)?;
+        let config = FilesystemConfig::with_backend(mounts);
+        assert_eq!(read_to_string_async("/core/virtualFolder/vfile1.txt", &config).await?, "hello, world! This is a test");
+
+        let overlay = Overlay::new()
+            .with_layer("base", FilesystemConfig::with_root("tests/assetpackage")?)
+            .with_layer("mods", FilesystemConfig::with_root("tests/overlay")?);
+        let config = FilesystemConfig::with_backend(overlay);
+        assert_eq!(read_to_string_async("virtualFolder/vfile1.txt", &config).await?, "This file overrides the one in the asset package");
+        assert_eq!(read_async("virtualFolder/vfile1-copy.txt", &config).await?, b"hello, world! This is a test");
+        assert!(matches!(read_async("virtualFolder/invented.txt", &config).await, Err(FilesystemError::NotFound(_))));
+        Ok(())
+    }
+}
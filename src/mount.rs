@@ -0,0 +1,293 @@
+//! A [`MountTable`] attaches several configurations to virtual path
+//! prefixes inside one namespace. For example, an Asset Package can be
+//! mounted at `/core/` and an Aura file at `/dlc/remote/`.
+//! 
+//! When a path is read, the prefix of the mount point is stripped and
+//! the rest of the path is read from the mounted configuration. If several
+//! mount points match a path, the longest one is used.
+
+use std::collections::BTreeMap;
+
+use crate::{backend::{Backend, BackendKind, DirEntry, Metadata, PrefetchReport, ReadSeek}, config::virtual_path, FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult};
+#[cfg(feature = "async")]
+use crate::backend::BoxFuture;
+#[cfg(feature = "aura")]
+use crate::readers::transfer::Transfer;
+
+/// A [`FilesystemConfig`] attached to a virtual path prefix
+#[derive(Debug)]
+struct MountPoint {
+    /// The prefix without leading or trailing slashes. Empty for the root.
+    prefix: String,
+    config: FilesystemConfig
+}
+
+impl MountPoint {
+    /// If `path` is inside this mount point, returns the path relative to it.
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.prefix.is_empty() {
+            return Some(path);
+        }
+        let rest = path.strip_prefix(self.prefix.as_str())?;
+        if rest.is_empty() {
+            Some(rest)
+        } else {
+            rest.strip_prefix('/')
+        }
+    }
+}
+
+/// A table of mount points. It implements [`Backend`], so it can be used in
+/// a [`FilesystemConfig`] with [`FilesystemConfig::with_backend`].
+#[derive(Debug, Default)]
+pub struct MountTable {
+    /// Sorted from the longest prefix to the shortest one
+    mounts: Vec<MountPoint>,
+    /// Options of the configuration that holds this table
+    options: FilesystemOptions
+}
+
+impl MountTable {
+    /// Constructs a mount table without any mount points
+    pub fn new() -> Self {
+        MountTable { mounts: Vec::new(), options: FilesystemOptions::default() }
+    }
+
+    /// Mounts a configuration at the indicated prefix and returns the table.
+    pub fn with_mount(mut self, prefix: &str, config: FilesystemConfig) -> FilesystemResult<Self> {
+        self.mount(prefix, config)?;
+        Ok(self)
+    }
+
+    /// Mounts a configuration at the indicated prefix. Mounting at `/` or an
+    /// empty prefix makes the configuration serve every path that isn't
+    /// covered by other mount points.
+    /// 
+    /// If something was already mounted at this prefix, it is replaced
+    /// and returned. The prefix is normalized with [`virtual_path::normalize`],
+    /// so this fails if it contains `..`.
+    /// 
+    /// The configuration inherits the options of the configuration that holds
+    /// this table, unless options were set on it.
+    pub fn mount(&mut self, prefix: &str, mut config: FilesystemConfig) -> FilesystemResult<Option<FilesystemConfig>> {
+        let prefix = virtual_path::normalize(prefix)?;
+        let previous = self.unmount(&prefix);
+        config.inherit_options(&self.options);
+
+        self.mounts.push(MountPoint { prefix, config });
+        self.mounts.sort_by_key(|mount| std::cmp::Reverse(mount.prefix.len()));
+        Ok(previous)
+    }
+
+    /// Removes the configuration mounted at the indicated prefix and returns it
+    pub fn unmount(&mut self, prefix: &str) -> Option<FilesystemConfig> {
+        let prefix = virtual_path::normalize(prefix).ok()?;
+        let position = self.mounts.iter().position(|mount| mount.prefix == prefix)?;
+        Some(self.mounts.remove(position).config)
+    }
+
+    /// Returns the configuration that serves the indicated path and the
+    /// path relative to that configuration.
+    /// 
+    /// This fails with [`FilesystemError::NotMounted`] if no mount point
+    /// covers the path.
+    /// 
+    /// The returned path is normalized with [`virtual_path::normalize`].
+    pub fn resolve(&self, path: &str) -> FilesystemResult<(&FilesystemConfig, String)> {
+        let normalized_path = virtual_path::normalize(path)?;
+        self.mounts
+            .iter()
+            .find_map(|mount| mount.strip(&normalized_path).map(|relative| (&mount.config, relative.to_string())))
+            .ok_or(FilesystemError::NotMounted(path.to_string()))
+    }
+
+    /// Returns the names of the mount points directly inside a directory,
+    /// such as `remote` for the directory `dlc` if something is mounted at `/dlc/remote/`.
+    fn mount_points_in(&self, directory: &str) -> Vec<String> {
+        self.mounts
+            .iter()
+            .filter_map(|mount| {
+                let rest = if directory.is_empty() {
+                    mount.prefix.as_str()
+                } else {
+                    mount.prefix.strip_prefix(directory)?.strip_prefix('/')?
+                };
+                let name = rest.split('/').next()?;
+                (!name.is_empty()).then(|| name.to_string())
+            })
+            .collect()
+    }
+}
+
+impl Backend for MountTable {
+    fn read(&self, path: &str) -> FilesystemResult<Vec<u8>> {
+        let (config, relative) = self.resolve(path)?;
+        config.backend().read(&relative)
+    }
+
+    fn read_to_string(&self, path: &str) -> FilesystemResult<String> {
+        let (config, relative) = self.resolve(path)?;
+        config.backend().read_to_string(&relative)
+    }
+
+    #[cfg(feature = "aura")]
+    fn read_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+        let (config, relative) = self.resolve(path)?;
+        config.backend().read_with(&relative, transfer)
+    }
+
+    #[cfg(feature = "aura")]
+    fn read_to_string_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<String> {
+        let (config, relative) = self.resolve(path)?;
+        config.backend().read_to_string_with(&relative, transfer)
+    }
+
+    #[cfg(feature = "async")]
+    fn read_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<Vec<u8>>> {
+        Box::pin(async move {
+            let (config, relative) = self.resolve(path)?;
+            config.backend().read_async(&relative).await
+        })
+    }
+
+    #[cfg(feature = "async")]
+    fn read_to_string_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<String>> {
+        Box::pin(async move {
+            let (config, relative) = self.resolve(path)?;
+            config.backend().read_to_string_async(&relative).await
+        })
+    }
+
+    fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        let (config, relative) = self.resolve(path)?;
+        config.backend().open(&relative)
+    }
+
+    fn metadata(&self, path: &str) -> FilesystemResult<Metadata> {
+        // Directories that contain mount points exist even if nothing is mounted there
+        let contains_mounts = !self.mount_points_in(&virtual_path::normalize(path)?).is_empty();
+        match self.resolve(path) {
+            Ok((config, relative)) => match config.backend().metadata(&relative) {
+                Err(FilesystemError::NotFound(_)) if contains_mounts => Ok(Metadata { is_dir: true, kind: BackendKind::Virtual, ..Default::default() }),
+                result => result,
+            },
+            Err(_) if contains_mounts => Ok(Metadata { is_dir: true, kind: BackendKind::Virtual, ..Default::default() }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Lists a directory of the mounted configuration and adds
+    /// the mount points that are inside that directory.
+    fn read_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>> {
+        let normalized_path = virtual_path::normalize(path)?;
+        let directory = normalized_path.as_str();
+        let mut entries: BTreeMap<String, DirEntry> = BTreeMap::new();
+
+        for name in self.mount_points_in(directory) {
+            let entry_path = if directory.is_empty() { name.clone() } else { format!("{directory}/{name}") };
+            entries.insert(name.clone(), DirEntry { path: entry_path, name, is_dir: true });
+        }
+
+        let mounted_entries = match self.resolve(path) {
+            Ok((config, relative)) => config.backend().read_dir(&relative),
+            Err(e) => Err(e),
+        };
+        match mounted_entries {
+            Ok(mounted_entries) => {
+                for mut entry in mounted_entries {
+                    entry.path = if directory.is_empty() { entry.name.clone() } else { format!("{directory}/{}", entry.name) };
+                    entries.entry(entry.name.clone()).or_insert(entry);
+                }
+            },
+            // Mount points are listed even if their parent directory isn't mounted
+            Err(FilesystemError::NotFound(_)) | Err(FilesystemError::NotMounted(_)) if !entries.is_empty() => {},
+            Err(e) => return Err(e),
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    /// Every path is prefetched by the configuration mounted there
+    fn prefetch(&self, paths: &[&str]) -> PrefetchReport {
+        PrefetchReport::from_configs(paths, |path| self.resolve(path))
+    }
+
+    /// Gives the options to every mounted configuration that doesn't have
+    /// options of its own
+    fn set_options(&mut self, options: &FilesystemOptions) {
+        self.options = options.clone();
+        for mount in &mut self.mounts {
+            mount.config.inherit_options(options);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{read_to_string, Backend, FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult, MountTable};
+
+    fn test_mounts() -> FilesystemResult<MountTable> {
+        MountTable::new()
+            .with_mount("/core/", FilesystemConfig::with_root("tests/assetpackage")?)?
+            .with_mount("/dlc/remote/", FilesystemConfig::with_root("tests/aura")?)
+    }
+
+    #[test]
+    fn read_from_mount_points() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_backend(test_mounts()?);
+        assert_eq!(read_to_string("/core/virtualFolder/vfile1.txt", &config)?, "hello, world! This is a test");
+        assert_eq!(read_to_string("core/otherFolder/someScript.lua", &config)?, "When The imposter is sus!! This is a script or something.");
+
+        assert!(matches!(read_to_string("/virtualFolder/vfile1.txt", &config), Err(FilesystemError::NotMounted(_))));
+        assert!(matches!(read_to_string("/corefile.txt", &config), Err(FilesystemError::NotMounted(_))));
+        assert!(matches!(read_to_string("/core/invented.txt", &config), Err(FilesystemError::NotFound(_))));
+        assert_eq!(read_to_string("\\core\\virtualFolder\\vfile1.txt", &config)?, "hello, world! This is a test");
+        assert!(matches!(read_to_string("/dlc/../core/virtualFolder/vfile1.txt", &config), Err(FilesystemError::OutOfBounds(_, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn apply_options_to_mounts() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_backend(test_mounts()?)
+            .with_options(FilesystemOptions::new().with_max_read_size(10));
+        assert!(matches!(read_to_string("/core/virtualFolder/vfile1.txt", &config), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+
+        // Configurations with their own options keep them
+        let core = FilesystemConfig::with_root("tests/assetpackage")?.with_options(FilesystemOptions::new().with_max_read_size(100));
+        let config = FilesystemConfig::with_backend(MountTable::new().with_mount("/core/", core)?)
+            .with_options(FilesystemOptions::new().with_max_read_size(10));
+        assert_eq!(read_to_string("/core/virtualFolder/vfile1.txt", &config)?, "hello, world! This is a test");
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_longest_prefix() -> FilesystemResult<()> {
+        let mounts = test_mounts()?
+            .with_mount("/", FilesystemConfig::with_root("tests")?)?;
+        let (config, relative) = mounts.resolve("/dlc/remote/binaries/example.bin")?;
+        assert_eq!(config.path(), "tests/aura/");
+        assert_eq!(relative, "binaries/example.bin");
+
+        let (config, relative) = mounts.resolve("/filesystem/testfile.txt")?;
+        assert_eq!(config.path(), "tests/");
+        assert_eq!(relative, "filesystem/testfile.txt");
+        Ok(())
+    }
+
+    #[test]
+    fn list_mount_points() -> FilesystemResult<()> {
+        let mounts = test_mounts()?;
+        let root: Vec<String> = mounts.read_dir("/")?.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(root, vec!["core", "dlc"]);
+
+        let dlc: Vec<String> = mounts.read_dir("/dlc")?.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(dlc, vec!["dlc/remote"]);
+
+        let remote: Vec<String> = mounts.read_dir("/dlc/remote")?.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(remote, vec!["dlc/remote/binaries", "dlc/remote/virtualFolder"]);
+
+        assert!(mounts.metadata("/dlc")?.is_dir);
+        assert!(matches!(mounts.read_dir("/invented"), Err(FilesystemError::NotMounted(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! An [`Overlay`] stacks several configurations on top of each other,
+//! like a base game package, a patch package and a folder of mods.
+//! 
+//! Files are looked up from the layer with the highest priority down
+//! to the one with the lowest priority. The first layer that contains
+//! a file is the one that serves it.
+
+use std::collections::BTreeMap;
+
+use crate::{backend::{Backend, DirEntry, Metadata, PrefetchReport, ReadSeek}, FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult};
+#[cfg(feature = "async")]
+use crate::backend::BoxFuture;
+#[cfg(feature = "aura")]
+use crate::readers::transfer::Transfer;
+
+/// A named [`FilesystemConfig`] inside an [`Overlay`]
+#[derive(Debug)]
+pub struct OverlayLayer {
+    name: String,
+    config: FilesystemConfig
+}
+
+impl OverlayLayer {
+    /// Returns the name of this layer
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the configuration of this layer
+    pub fn config(&self) -> &FilesystemConfig {
+        &self.config
+    }
+}
+
+/// A layered filesystem. It implements [`Backend`], so it can be used in
+/// a [`FilesystemConfig`] with [`FilesystemConfig::with_backend`].
+/// 
+/// Layers are added from the lowest priority to the highest priority:
+/// the last layer added is the first one to be checked.
+#[derive(Debug, Default)]
+pub struct Overlay {
+    /// Sorted from lowest to highest priority
+    layers: Vec<OverlayLayer>,
+    /// Options of the configuration that holds this overlay
+    options: FilesystemOptions
+}
+
+impl Overlay {
+    /// Constructs an overlay without any layers
+    pub fn new() -> Self {
+        Overlay { layers: Vec::new(), options: FilesystemOptions::default() }
+    }
+
+    /// Adds a layer on top of every other layer and returns the overlay.
+    pub fn with_layer(mut self, name: &str, config: FilesystemConfig) -> Self {
+        self.push_layer(name, config);
+        self
+    }
+
+    /// Adds a layer on top of every other layer.
+    /// 
+    /// The layer inherits the options of the configuration that holds this
+    /// overlay, unless options were set on its own configuration.
+    pub fn push_layer(&mut self, name: &str, mut config: FilesystemConfig) {
+        config.inherit_options(&self.options);
+        self.layers.push(OverlayLayer { name: name.to_string(), config });
+    }
+
+    /// Returns an iterator over the layers, from the highest priority
+    /// to the lowest priority.
+    pub fn layers(&self) -> impl Iterator<Item = &OverlayLayer> {
+        self.layers.iter().rev()
+    }
+
+    /// Returns the layer that serves the file in the indicated path.
+    /// 
+    /// This fails with [`FilesystemError::NotFound`] if no layer contains
+    /// the file. Any other error is returned as-is, lower layers are not
+    /// checked in that case.
+    pub fn resolve(&self, path: &str) -> FilesystemResult<&OverlayLayer> {
+        for layer in self.layers() {
+            match layer.config.backend().metadata(path) {
+                Ok(metadata) if !metadata.is_dir => return Ok(layer),
+                Ok(_) => return Err(FilesystemError::IsADirectory(path.to_string())),
+                Err(FilesystemError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(FilesystemError::NotFound(path.to_string()))
+    }
+
+    /// Returns the backend of the layer that serves the indicated path
+    fn backend_for(&self, path: &str) -> FilesystemResult<&dyn Backend> {
+        Ok(self.resolve(path)?.config.backend())
+    }
+
+    /// Reads a file asynchronously from the first layer that has it. Looking up
+    /// the layer with [`Overlay::resolve`] would block, so every layer is read
+    /// instead, from the highest priority down, until one doesn't fail with
+    /// [`FilesystemError::NotFound`].
+    #[cfg(feature = "async")]
+    async fn read_first_async<'a, T>(&'a self, path: &'a str, read: impl Fn(&'a dyn Backend) -> BoxFuture<'a, FilesystemResult<T>>) -> FilesystemResult<T> {
+        for layer in self.layers() {
+            match read(layer.config.backend()).await {
+                Err(FilesystemError::NotFound(_)) => continue,
+                result => return result,
+            }
+        }
+        Err(FilesystemError::NotFound(path.to_string()))
+    }
+}
+
+impl Backend for Overlay {
+    fn read(&self, path: &str) -> FilesystemResult<Vec<u8>> {
+        self.backend_for(path)?.read(path)
+    }
+
+    fn read_to_string(&self, path: &str) -> FilesystemResult<String> {
+        self.backend_for(path)?.read_to_string(path)
+    }
+
+    #[cfg(feature = "aura")]
+    fn read_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+        self.backend_for(path)?.read_with(path, transfer)
+    }
+
+    #[cfg(feature = "aura")]
+    fn read_to_string_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<String> {
+        self.backend_for(path)?.read_to_string_with(path, transfer)
+    }
+
+    #[cfg(feature = "async")]
+    fn read_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<Vec<u8>>> {
+        Box::pin(self.read_first_async(path, move |backend| backend.read_async(path)))
+    }
+
+    #[cfg(feature = "async")]
+    fn read_to_string_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<String>> {
+        Box::pin(self.read_first_async(path, move |backend| backend.read_to_string_async(path)))
+    }
+
+    fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        self.backend_for(path)?.open(path)
+    }
+
+    fn metadata(&self, path: &str) -> FilesystemResult<Metadata> {
+        for layer in self.layers() {
+            match layer.config.backend().metadata(path) {
+                Err(FilesystemError::NotFound(_)) => continue,
+                result => return result,
+            }
+        }
+        Err(FilesystemError::NotFound(path.to_string()))
+    }
+
+    /// Merges the directory listings of every layer that contains the directory.
+    fn read_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>> {
+        let mut entries: BTreeMap<String, DirEntry> = BTreeMap::new();
+        let mut found = false;
+        for layer in self.layers() {
+            let layer_entries = match layer.config.backend().read_dir(path) {
+                Ok(e) => e,
+                Err(FilesystemError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            found = true;
+            for entry in layer_entries {
+                // Higher layers were inserted first, they take precedence
+                entries.entry(entry.name.clone()).or_insert(entry);
+            }
+        }
+
+        if !found {
+            return Err(FilesystemError::NotFound(path.to_string()));
+        }
+        Ok(entries.into_values().collect())
+    }
+
+    /// Every path is prefetched by the layer that serves it
+    fn prefetch(&self, paths: &[&str]) -> PrefetchReport {
+        PrefetchReport::from_configs(paths, |path| Ok((&self.resolve(path)?.config, path.to_string())))
+    }
+
+    /// Gives the options to every layer that doesn't have options of its own
+    fn set_options(&mut self, options: &FilesystemOptions) {
+        self.options = options.clone();
+        for layer in &mut self.layers {
+            layer.config.inherit_options(options);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{read_to_string, Backend, FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult, Overlay};
+
+    fn test_overlay() -> FilesystemResult<Overlay> {
+        Ok(Overlay::new()
+            .with_layer("base", FilesystemConfig::with_root("tests/assetpackage")?)
+            .with_layer("mods", FilesystemConfig::with_root("tests/overlay")?))
+    }
+
+    #[test]
+    fn read_through_layers() -> FilesystemResult<()> {
+        let overlay = test_overlay()?;
+
+        assert_eq!(overlay.resolve("virtualFolder/vfile1.txt")?.name(), "mods");
+        assert_eq!(overlay.resolve("virtualFolder/vfile1-copy.txt")?.name(), "base");
+        assert!(overlay.resolve("virtualFolder/invented.txt").is_err());
+
+        let config = FilesystemConfig::with_backend(overlay);
+        assert_eq!(read_to_string("virtualFolder/vfile1.txt", &config)?, "This file overrides the one in the asset package");
+        assert_eq!(read_to_string("virtualFolder/vfile1-copy.txt", &config)?, "hello, world! This is a test");
+        assert_eq!(read_to_string("virtualFolder/modded.txt", &config)?, "This file only exists in the overlay");
+        Ok(())
+    }
+
+    #[test]
+    fn list_merged_layers() -> FilesystemResult<()> {
+        let overlay = test_overlay()?;
+        let names: Vec<String> = overlay.read_dir("virtualFolder")?.into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, vec!["modded.txt", "vfile1-copy.txt", "vfile1.txt"]);
+
+        let layer_names: Vec<&str> = overlay.layers().map(|layer| layer.name()).collect();
+        assert_eq!(layer_names, vec!["mods", "base"]);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_options_to_layers() -> FilesystemResult<()> {
+        let config = FilesystemConfig::with_backend(test_overlay()?)
+            .with_options(FilesystemOptions::new().with_max_read_size(10));
+        assert!(matches!(read_to_string("virtualFolder/vfile1-copy.txt", &config), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+
+        // Layers with their own options keep them
+        let base = FilesystemConfig::with_root("tests/assetpackage")?.with_options(FilesystemOptions::new().with_max_read_size(100));
+        let config = FilesystemConfig::with_backend(Overlay::new().with_layer("base", base))
+            .with_options(FilesystemOptions::new().with_max_read_size(10));
+        assert_eq!(read_to_string("virtualFolder/vfile1-copy.txt", &config)?, "hello, world! This is a test");
+        Ok(())
+    }
+}
@@ -3,11 +3,20 @@
 //! 
 //! This requires an input directory that we can recursively read and an
 //! output directory for the package an index
+//! 
+//! With the `aura` feature, the files of an indexed configuration (like an
+//! Aura index) can also be downloaded into a package with [`pack_aura`].
+
+use std::{fs::{self, File, OpenOptions}, io::{self, BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}, process, sync::atomic::{AtomicU64, Ordering}};
 
-use std::{fs::{self, File, OpenOptions}, io::{self, BufReader, BufWriter, Write}, path::{Path, PathBuf}};
+#[cfg(feature = "aura")]
+use crate::FilesystemConfig;
 
 use crate::{config::{assetmap::AssetMap, index::{AssetPackIndex, IndexFile, IndexType}, secure_path::BoundChecker}, FilesystemError, FilesystemResult};
 
+/// Used to give every temporary file of this process a different name
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
+
 /// Used when reading 
 struct FsObjectsList {
     files: Vec<PathBuf>,
@@ -15,40 +24,39 @@ struct FsObjectsList {
 }
 
 struct OutputPackageFile {
+    /// Temporary file the package is written to until it's complete
     path: PathBuf,
+    /// File name of the finished package, used in the index
+    name: String,
     writer: BufWriter<File>,
     current_size: u64
 }
 
 impl OutputPackageFile {
+    /// Creates the temporary file of a package that will be saved as `path`
     pub fn new(path: &Path) -> FilesystemResult<Self> {
-        Self::delete_file(path)?;
+        let Some(name) = path.file_name() else {
+            return Err(FilesystemError::Generic(path_to_string(path), "Packages need a file name".to_string()));
+        };
+        let temporary_path = temporary_path(path);
 
         let destination = OpenOptions::new()
-            .write(true)
             .append(true)
             .create_new(true)
-            .open(path)
-            .map_err(|e| FilesystemError::from(e).with_path(path_to_string(path)))?
+            .open(&temporary_path)
+            .map_err(|e| FilesystemError::from(e).with_path(path_to_string(&temporary_path)))?
         ;
         let writer = BufWriter::new(destination);
 
         Ok(
             OutputPackageFile {
-                path: path.to_path_buf(),
+                path: temporary_path,
+                name: name.to_string_lossy().to_string(),
                 current_size: 0,
                 writer
             }
         )
     }
-
-    /// If the file already exists, deletes it.
-    fn delete_file(path: &Path) -> FilesystemResult<()> {
-        if path.exists() {
-            fs::remove_file(path)?;
-        }
-        Ok(())
-    } 
 }
 
 /// Converts a path into a String. Invalid unicode will get f*cked but
@@ -57,6 +65,16 @@ fn path_to_string(path: &Path) -> String {
     path.as_os_str().to_string_lossy().to_string()
 }
 
+/// Returns a new path a file can be written to before it's renamed to `path`.
+/// Every call returns a different path so packagers writing the same output
+/// don't overwrite each other's files.
+fn temporary_path(path: &Path) -> PathBuf {
+    let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}-{count}.part", process::id()));
+    path.with_file_name(name)
+}
+
 /// Scans a directory and stores all subdirectories and files
 /// in a FsObjectsList
 fn scan_directory(directory: &Path) -> FilesystemResult<FsObjectsList> {
@@ -109,25 +127,46 @@ fn append_file(bound_checker: &BoundChecker, input_file: &Path, output_file: &mu
     // This function already checks for bounds so if the function continues it means the file is in bounds.
     let relative_path = bound_checker.get_relative_string(input_file)?;
 
-    // write to output file
     let source = File::open(input_file).map_err(|e| FilesystemError::from(e).with_path(path_to_string(input_file)))?;
-    let file_size = source
-        .metadata()
-        .map_err(|e| FilesystemError::from(e).with_path(path_to_string(input_file)))?
-        .len();
+    append_reader(&mut BufReader::new(source), &path_to_string(input_file), relative_path, output_file, asset_map)
+}
 
-    let mut reader = BufReader::new(source);
+/// Converts an error of a reader into a [`FilesystemError`]. Readers of Aura
+/// files wrap their errors (like hash mismatches) in an [`io::Error`], those
+/// are returned as they are.
+fn read_error(source: &str, error: io::Error) -> FilesystemError {
+    match error.get_ref().is_some_and(|inner| inner.is::<FilesystemError>()) {
+        true => *error.into_inner().and_then(|inner| inner.downcast().ok()).expect("Shouldn't fail, we just checked the type"),
+        false => FilesystemError::from(error).with_path(source.to_string()),
+    }
+}
 
+/// Appends everything a reader returns into a destination file and registers
+/// it in the provided [`AssetMap`] as `relative_path`. `source` is only used
+/// in errors.
+fn append_reader(reader: &mut dyn Read, source: &str, relative_path: String, output_file: &mut OutputPackageFile, asset_map: &mut AssetMap) -> FilesystemResult<()> {
     // Get starting index first
     let starting_index = output_file.current_size;
 
-    io::copy(&mut reader, &mut output_file.writer).map_err(|e| FilesystemError::from(e).with_path(path_to_string(&output_file.path)))?;
+    // Errors of the reader and the package are reported with their own path
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut file_size = 0;
+    loop {
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(read_error(source, e)),
+        };
+        output_file.writer.write_all(&buffer[..bytes_read]).map_err(|e| FilesystemError::from(e).with_path(path_to_string(&output_file.path)))?;
+        file_size += bytes_read as u64;
+    }
     output_file.writer.flush().map_err(|e| FilesystemError::from(e).with_path(path_to_string(&output_file.path)))?;
 
     // If everything was right we can register the Asset Map Entry and update the file size
     let index_type = IndexType::AssetPack(AssetPackIndex {
         // for the package we only want the package name, we expect the index and package to be in the same place
-        package: output_file.path.file_name().expect("Shouldn't fail, we already wrote to this file").to_string_lossy().to_string(),
+        package: output_file.name.clone(),
         file_size,
         starting_index
     });
@@ -144,31 +183,122 @@ fn append_file(bound_checker: &BoundChecker, input_file: &Path, output_file: &mu
 /// loaded into memory.
 pub fn pack(input: &Path, output: &Path, name_no_extension: &str) -> FilesystemResult<()> {
     let bound_checker = BoundChecker::new(input)?;
-    let mut asset_map = AssetMap::new();
 
     let files = scan_directory_recursively(input)?;
     
-    // Create output file
-    let package_name = name_no_extension.to_string() + ".oap";
-    let mut output_file = OutputPackageFile::new(&output.join(package_name))?;
-
     // Create the package
-    for file in files {
-        append_file(&bound_checker, &file, &mut output_file, &mut asset_map)?;
+    write_package(output, name_no_extension, |output_file, asset_map| {
+        for file in files {
+            append_file(&bound_checker, &file, output_file, asset_map)?;
+        }
+        Ok(())
+    })
+}
+
+/// Downloads every file of an indexed configuration (like one loaded from an
+/// Aura index) and saves them as an Asset Package and its index in the output
+/// directory, so they can be read without the network.
+/// 
+/// Files are read with the options of the configuration (cache, mirrors, size
+/// limits...) and streamed into the package, so large files are never fully
+/// loaded into memory. Hashes are checked while the files are copied: a file
+/// that doesn't match its hash stops the packaging with
+/// [`FilesystemError::HashMismatch`]. Files that are already in Asset Packages
+/// are copied too, so the new index doesn't depend on the original packages.
+/// 
+/// An existing package and index are only replaced when every file was
+/// copied, a failure leaves them untouched.
+/// 
+/// Unindexed configurations fail with [`FilesystemError::UnindexedFilesystem`].
+#[cfg(feature = "aura")]
+pub fn pack_aura(config: &FilesystemConfig, output: &Path, name_no_extension: &str) -> FilesystemResult<()> {
+    let Some(index) = config.backend().asset_map() else {
+        return Err(FilesystemError::UnindexedFilesystem(config.path()));
+    };
+
+    // Sorted so the same index always produces the same package
+    let mut paths: Vec<&String> = index.keys().collect();
+    paths.sort();
+
+    write_package(output, name_no_extension, |output_file, asset_map| {
+        for path in paths {
+            let mut reader = crate::open(path, config)?;
+            append_reader(&mut reader, path, path.clone(), output_file, asset_map)?;
+        }
+        Ok(())
+    })
+}
+
+/// Builds a package with `append` and saves it and its index into the output
+/// directory.
+/// 
+/// Both files are written to temporary files first and only replace the
+/// existing package and index once everything was written. If anything fails,
+/// the temporary files are removed and the existing ones are left untouched
+/// (see [`replace_package`]).
+fn write_package(output: &Path, name_no_extension: &str, append: impl FnOnce(&mut OutputPackageFile, &mut AssetMap) -> FilesystemResult<()>) -> FilesystemResult<()> {
+    let package_path = output.join(name_no_extension.to_string() + ".oap");
+    let index_path = output.join(name_no_extension.to_string() + ".oroi");
+
+    let mut output_file = OutputPackageFile::new(&package_path)?;
+    let mut asset_map = AssetMap::new();
+    let result = append(&mut output_file, &mut asset_map);
+
+    // The package is closed before it's renamed
+    let temporary_package = output_file.path.clone();
+    drop(output_file);
+    let temporary_index = temporary_path(&index_path);
+    let result = result
+        .and_then(|()| write_index(asset_map, &temporary_index))
+        .and_then(|()| replace_package(&temporary_package, &package_path, &temporary_index, &index_path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_package);
+        let _ = fs::remove_file(&temporary_index);
     }
+    result
+}
 
-    // Serialize and export
+/// Saves the index of a package into the indicated path
+fn write_index(asset_map: AssetMap, index_file_path: &Path) -> FilesystemResult<()> {
     let index_file: IndexFile = asset_map.into();
     let index_file_serialized = serde_json::to_string(&index_file.files)
         .map_err(|e| FilesystemError::SerializationError(e.to_string()))?
     ;
-    let index_file_path = output.join(name_no_extension.to_string() + ".oroi");
-    fs::write(&index_file_path, index_file_serialized)
-        .map_err(|e| FilesystemError::from(e).with_path(path_to_string(&index_file_path)))?
+    fs::write(index_file_path, index_file_serialized)
+        .map_err(|e| FilesystemError::from(e).with_path(path_to_string(index_file_path)))?
     ;
     Ok(())
 }
 
+/// Renames a finished package and its index into place. The previous package
+/// is kept until the index was replaced too: if that fails, it's restored so
+/// it never ends up next to an index of another package.
+fn replace_package(temporary_package: &Path, package_path: &Path, temporary_index: &Path, index_path: &Path) -> FilesystemResult<()> {
+    let previous_package = temporary_path(package_path);
+    let has_previous = match fs::rename(package_path, &previous_package) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(FilesystemError::from(e).with_path(path_to_string(package_path))),
+    };
+
+    let result = rename(temporary_package, package_path)
+        .and_then(|()| rename(temporary_index, index_path));
+
+    let _ = match (&result, has_previous) {
+        (Ok(()), true) => fs::remove_file(&previous_package),
+        (Ok(()), false) => Ok(()),
+        (Err(_), true) => fs::rename(&previous_package, package_path),
+        (Err(_), false) => fs::remove_file(package_path),
+    };
+    result
+}
+
+/// Renames a finished file, replacing the existing one
+fn rename(from: &Path, to: &Path) -> FilesystemResult<()> {
+    fs::rename(from, to).map_err(|e| FilesystemError::from(e).with_path(path_to_string(to)))
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, path::Path};
@@ -184,7 +314,7 @@ mod tests {
 
         // Tests that files are correct
         assert_eq!(output.files.len(), 1);
-        assert_eq!(fs::read_to_string(output.files.get(0).unwrap()).unwrap(), "This tests that the scanner can read files and directories".to_string());
+        assert_eq!(fs::read_to_string(output.files.first().unwrap()).unwrap(), "This tests that the scanner can read files and directories".to_string());
 
         Ok(())
     }
@@ -204,4 +334,70 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn failed_index_keeps_package() -> FilesystemResult<()> {
+        let output = std::env::temp_dir().join("oro-filesystem-tests").join("failed-index");
+        let _ = fs::remove_dir_all(&output);
+        fs::create_dir_all(output.join("broken.oroi"))?;
+        fs::write(output.join("broken.oroi").join("file.txt"), "The index can't replace a directory")?;
+        fs::write(output.join("broken.oap"), "Previous package")?;
+
+        // The new package is moved into place but the index can't be, so the previous package is restored
+        assert!(pack(Path::new("tests"), &output, "broken").is_err());
+        assert_eq!(fs::read_to_string(output.join("broken.oap"))?, "Previous package");
+        assert_eq!(fs::read_dir(&output)?.count(), 2);
+
+        // Without a previous package, the new one is removed
+        fs::remove_file(output.join("broken.oap"))?;
+        assert!(pack(Path::new("tests"), &output, "broken").is_err());
+        assert_eq!(fs::read_dir(&output)?.count(), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn pack_aura_index() -> FilesystemResult<()> {
+        use crate::{
+            config::{assetmap::AssetMap, index::{AuraIndex, IndexType}},
+            extra::IndexedBackend,
+            pack_aura, test_server, FilesystemError
+        };
+
+        let server = test_server::TestServer::start();
+        let output = std::env::temp_dir().join("oro-filesystem-tests").join("pack-aura");
+        let _ = fs::remove_dir_all(&output);
+        fs::create_dir_all(&output)?;
+
+        let config = FilesystemConfig::from_index_url(&server.url("/webmod.oroi"))?;
+        pack_aura(&config, &output, "webmod")?;
+
+        let packed = FilesystemConfig::with_index(&output.to_string_lossy(), "webmod.oroi")?;
+        assert!(matches!(packed.get_index_for_file("binaries/example.bin")?, IndexType::AssetPack(index) if index.package == "webmod.oap"));
+        assert_eq!(crate::read("binaries/example.bin", &packed)?, test_server::BINARY);
+        assert_eq!(crate::read_to_string("virtualFolder/vfile1.txt", &packed)?, test_server::TEXT);
+        assert_eq!(crate::read_to_string("virtualFolder/vfile1-copy.txt", &packed)?, test_server::TEXT);
+
+        // Files that don't match their hash stop the packaging
+        let mut index = AssetMap::new();
+        index.insert("tampered.txt".to_string(), IndexType::Aura(AuraIndex {
+            url: server.url("/raw/t0qjYDWt"),
+            hash: Some("sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f".to_string()),
+            mirrors: Vec::new()
+        }));
+        let tampered = FilesystemConfig::with_backend(IndexedBackend::new("", index));
+        assert!(matches!(pack_aura(&tampered, &output, "tampered"), Err(FilesystemError::HashMismatch(..))));
+        assert!(!output.join("tampered.oap").exists());
+
+        // A failed packaging leaves the existing package and index untouched
+        let package = fs::read(output.join("webmod.oap"))?;
+        assert!(matches!(pack_aura(&tampered, &output, "webmod"), Err(FilesystemError::HashMismatch(..))));
+        assert_eq!(fs::read(output.join("webmod.oap"))?, package);
+        assert_eq!(crate::read_to_string("virtualFolder/vfile1.txt", &packed)?, test_server::TEXT);
+        assert_eq!(fs::read_dir(&output)?.count(), 2);
+
+        let unindexed = FilesystemConfig::with_root("tests/filesystem")?;
+        assert!(matches!(pack_aura(&unindexed, &output, "unindexed"), Err(FilesystemError::UnindexedFilesystem(_))));
+        Ok(())
+    }
+}
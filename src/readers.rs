@@ -28,17 +28,41 @@ pub enum FilesystemError {
     /// The parameter is the error message obtained from [`serde_json`].
     SerializationError(String),
     /// An index file contained multiple definitions for one single
-    /// file
-    DuplicatePathsInIndex(String),
+    /// file. The first parameter is the path, the second one contains the names
+    /// of the index files that define it (empty if they are unknown).
+    DuplicatePathsInIndex(String, Vec<String>),
     /// Attempted to get index information from an unindexed filesystem (Native Filesystem)
     UnindexedFilesystem(String),
-    /// Any error that happens during the fetch of a web resource. The first parameter is the URL
-    /// and the second one is the error message.
-    FetchError(String, String),
+    /// Any error that happens during the fetch of a web resource. The first parameter is the URL,
+    /// the second one is the error message and the third one is the HTTP status code of the
+    /// response, if the server sent one.
+    /// 
+    /// `404`/`410` responses are reported as [`FilesystemError::NotFound`] and `401`/`403`
+    /// responses as [`FilesystemError::PermissionDenied`] instead.
+    FetchError(String, String, Option<u16>),
     /// Attempted to read a file or directory that should be inside a specific directory
     /// but isn't. This can be as simple as "the user tried to read the filesystem root" but
     /// it can also be triggered by trying to access parent directories with "..".
     OutOfBounds(String, String),
+    /// Downloaded data didn't match the hash in its index. The first parameter is the URL,
+    /// the second one is the expected hash and the third one is the hash of the data.
+    HashMismatch(String, String, String),
+    /// Attempted to download a web resource in offline mode and it wasn't cached.
+    /// The parameter is the URL.
+    Offline(String),
+    /// Attempted to read a path that isn't covered by any mount point of a [`crate::MountTable`]
+    NotMounted(String),
+    /// Attempted to read a file that needs a cargo feature of ORO Filesystem that is
+    /// disabled. The first parameter is the path and the second one is the feature
+    /// (for example, Aura files need the `aura` feature).
+    FeatureDisabled(String, String),
+    /// A download was stopped with a cancellation token. The parameter is the URL.
+    Cancelled(String),
+    /// Reading a file would go over a size limit of the [`crate::FilesystemOptions`].
+    /// The first parameter is the path or URL and the second one is the limit in bytes
+    /// (see [`crate::FilesystemOptions::with_max_read_size`] and
+    /// [`crate::FilesystemOptions::with_max_total_size`]).
+    SizeLimitExceeded(String, u64),
     /// Any other type of error that I didn't want to add into this enum.   
     /// The first parameter is the path, the second one is the actual error's `to_string()`
     Generic(String, String)
@@ -53,12 +77,19 @@ impl Display for FilesystemError {
             FilesystemError::IsADirectory(path) => write!(f, "The \"file\" was actually secretly a directory \"{path}\""),
             FilesystemError::UnexpectedEof(path) => write!(f, "Unexpected end of file in file \"{path}\""),
             FilesystemError::OutOfMemory(path) => write!(f, "Can't load this chunky-ass file (out of memory): \"{path}\""),
-            FilesystemError::DuplicatePathsInIndex(path) => write!(f, "Duplicate path found in index file: {path}"),
+            FilesystemError::DuplicatePathsInIndex(path, sources) => write!(f, "Duplicate path found in index file: {path}{}", if sources.is_empty() {String::new()} else {format!(" (defined in \"{}\")", sources.join("\", \""))}),
             FilesystemError::DeserializationError(message) => write!(f, "Couldn't deserialize. Obtained error: {message}"),
             FilesystemError::SerializationError(message) => write!(f, "Couldn't serialize. Obtained error: {message}"),
             FilesystemError::UnindexedFilesystem(path) => write!(f, "Couldn't obtain index for file at \"{path}\". Filesystem is unindexed"),
-            FilesystemError::FetchError(url, errormsg) => write!(f, "Couldn't fetch web resource at \"{url}\". Reason: {errormsg}"),
+            FilesystemError::FetchError(url, errormsg, None) => write!(f, "Couldn't fetch web resource at \"{url}\". Reason: {errormsg}"),
+            FilesystemError::FetchError(url, errormsg, Some(status)) => write!(f, "Couldn't fetch web resource at \"{url}\" (HTTP {status}). Reason: {errormsg}"),
             FilesystemError::OutOfBounds(path, root) => write!(f, "Can't access \"{path}\". Resource outside directory \"{root}\""),
+            FilesystemError::HashMismatch(url, expected, actual) => write!(f, "Integrity check failed for \"{url}\". Expected {expected} but the data has {actual}"),
+            FilesystemError::Offline(url) => write!(f, "Couldn't fetch web resource at \"{url}\". Offline mode is enabled and it isn't cached"),
+            FilesystemError::NotMounted(path) => write!(f, "Can't access \"{path}\". No filesystem is mounted there"),
+            FilesystemError::FeatureDisabled(path, feature) => write!(f, "Can't read \"{path}\". It needs the \"{feature}\" feature of ORO Filesystem, which is disabled"),
+            FilesystemError::Cancelled(url) => write!(f, "The download of \"{url}\" was cancelled"),
+            FilesystemError::SizeLimitExceeded(path, limit) => write!(f, "Can't read \"{path}\". It goes over the limit of {limit} bytes"),
             FilesystemError::Generic(path, reason) => write!(f, "Couldn't read \"{path}\".{}", if reason.is_empty() {String::new()} else {String::from(" Reason: ")+ reason}),
         }
     }
@@ -106,4 +137,18 @@ pub type FilesystemResult<T> = Result<T, FilesystemError>;
 
 pub mod filesystem;
 pub mod assetpackage;
-pub mod aura;
\ No newline at end of file
+#[cfg(feature = "aura")]
+pub mod aura;
+#[cfg(feature = "aura")]
+pub mod cache;
+#[cfg(feature = "aura")]
+pub mod http;
+#[cfg(feature = "aura")]
+pub mod mirrors;
+#[cfg(feature = "aura")]
+pub mod scheme;
+#[cfg(feature = "aura")]
+pub mod transfer;
+pub mod indexed;
+pub mod integrity;
+pub mod limits;
\ No newline at end of file
@@ -1,33 +1,240 @@
 use std::{
     fs::File,
-    io::{Read, Seek, SeekFrom},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
 };
 
+#[cfg(feature = "aura")]
+use reqwest::StatusCode;
+
 use crate::{
-    config::index::AssetPackIndex, FilesystemError,
+    config::{index::AssetPackIndex, secure_path::BoundChecker}, FilesystemError,
 };
 
 use super::FilesystemResult;
+#[cfg(feature = "aura")]
+use super::{aura::AuraStream, http::HttpClient, transfer::{self, Transfer, TransferReader}};
 
-pub fn read(path: &str, root: &str, index: &AssetPackIndex) -> FilesystemResult<Vec<u8>> {
-    let package_path = String::from(root) + &index.package;
+/// A file inside an Asset Package, opened for reading.
+/// 
+/// Reads and seeks are bounded to the bytes of that file, the rest of the
+/// package can't be read through this reader. Local packages are read from
+/// a [`File`] and remote packages from a stream of the HTTP response.
+#[derive(Debug)]
+pub struct PackageEntryReader<R = File> {
+    package: R,
+    /// Index of the first byte of the file in the package
+    start: u64,
+    /// Size of the file
+    size: u64,
+    /// Current position, relative to `start`
+    position: u64
+}
+
+impl<R: Read> Read for PackageEntryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let bytes_read = self.package.read(&mut buf[..max])?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Seek> Seek for PackageEntryReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        let target = target.ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+
+        // Seeking past the end is allowed, reads will return 0 bytes
+        self.package.seek(SeekFrom::Start(self.start.saturating_add(target)))?;
+        self.position = target;
+        Ok(target)
+    }
+}
+
+/// Returns the canonical path of the package of an entry. Indices can't be
+/// trusted, so packages outside of `root` fail with [`FilesystemError::OutOfBounds`].
+fn package_path(root: &str, index: &AssetPackIndex) -> FilesystemResult<PathBuf> {
+    let root = Path::new(if root.is_empty() { "." } else { root });
+    BoundChecker::new(root)?.ensure_in_bounds(&root.join(&index.package))
+}
+
+/// Asynchronous version of [`package_path`]. Checking the bounds reads
+/// the filesystem, so it runs in a blocking thread.
+#[cfg(feature = "async")]
+async fn package_path_async(path: &str, root: &str, index: &AssetPackIndex) -> FilesystemResult<PathBuf> {
+    let root = root.to_string();
+    let index = index.clone();
+    tokio::task::spawn_blocking(move || package_path(&root, &index))
+        .await
+        .map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))?
+}
+
+/// Opens a file of an Asset Package for reading.
+/// 
+/// This fails with [`FilesystemError::OutOfBounds`] if the package is outside
+/// of `root` and with [`FilesystemError::UnexpectedEof`] if the package is
+/// too small to contain the file.
+pub fn open(path: &str, root: &str, index: &AssetPackIndex) -> FilesystemResult<PackageEntryReader> {
+    let package_path = package_path(root, index)?;
 
     let mut package = File::open(&package_path).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))?;
+    let package_size = package.metadata().map_err(|e| FilesystemError::from(e).with_path(path.to_string()))?.len();
+
+    // this can happen if the file doesn't have that many bytes.
+    if index.starting_index.checked_add(index.file_size).is_none_or(|end| end > package_size) {
+        return Err(FilesystemError::UnexpectedEof(path.to_string()));
+    }
 
     // apply file offset (which could fail and return an std::io::error)
     if let Err(e) = package.seek(SeekFrom::Start(index.starting_index)) {
         return Err(FilesystemError::from(e).with_path(path.to_string()));
     }
 
+    Ok(PackageEntryReader {
+        package,
+        start: index.starting_index,
+        size: index.file_size,
+        position: 0
+    })
+}
+
+/// Opens a file of a remote Asset Package (one whose `package` is a URL)
+/// for reading. The file is read while it's being downloaded.
+/// 
+/// Files of remote packages are never cached, they're requested again every time they're read.
+/// 
+/// This fails with [`FilesystemError::UnexpectedEof`] if the server reports
+/// that the package is too small to contain the file.
+#[cfg(feature = "aura")]
+pub fn open_remote(path: &str, client: &HttpClient, index: &AssetPackIndex, transfer: &Transfer) -> FilesystemResult<PackageEntryReader<TransferReader<AuraStream>>> {
+    transfer.check(&index.package)?;
+    let package = AuraStream::starting_at(client, &index.package, index.starting_index).map_err(|e| range_error(path, e))?;
+
+    if package.len().is_some_and(|package_size| index.starting_index.checked_add(index.file_size).is_none_or(|end| end > package_size)) {
+        return Err(FilesystemError::UnexpectedEof(path.to_string()));
+    }
+
+    Ok(PackageEntryReader {
+        package: TransferReader::new(package, &index.package, transfer, Some(index.file_size)),
+        start: index.starting_index,
+        size: index.file_size,
+        position: 0
+    })
+}
+
+/// Reads a file of a remote Asset Package (one whose `package` is a URL) by
+/// requesting only its bytes with a `Range` header.
+/// 
+/// The file is never cached, it's requested again every time it's read.
+/// 
+/// Servers that ignore the header send the whole package. In that case the
+/// bytes before the file are discarded and the download stops after the file.
+/// 
+/// The progress of the download is reported to `transfer` with the size of
+/// the file as the total.
+#[cfg(feature = "aura")]
+pub fn read_remote(path: &str, client: &HttpClient, index: &AssetPackIndex, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+    if index.file_size == 0 {
+        return Ok(Vec::new());
+    }
+    transfer.check(&index.package)?;
+    let end = index.starting_index.checked_add(index.file_size - 1).ok_or(FilesystemError::UnexpectedEof(path.to_string()))?;
+    let mut response = client.get_range(&index.package, index.starting_index, Some(end)).map_err(|e| range_error(path, e))?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        // The server ignored the Range header and sent the whole package
+        io::copy(&mut (&mut response).take(index.starting_index), &mut io::sink()).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))?;
+    }
+
+    let mut buffer = Vec::new();
+    TransferReader::new(response.take(index.file_size), &index.package, transfer, Some(index.file_size))
+        .read_to_end(&mut buffer)
+        .map_err(|e| transfer::from_io_error(&index.package, e))?;
+    if (buffer.len() as u64) < index.file_size {
+        return Err(FilesystemError::UnexpectedEof(path.to_string()));
+    }
+    Ok(buffer)
+}
+
+/// Asynchronous version of [`read_remote`]
+#[cfg(all(feature = "aura", feature = "async"))]
+pub async fn read_remote_async(path: &str, client: &HttpClient, index: &AssetPackIndex, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+    if index.file_size == 0 {
+        return Ok(Vec::new());
+    }
+    transfer.check(&index.package)?;
+    let end = index.starting_index.checked_add(index.file_size - 1).ok_or(FilesystemError::UnexpectedEof(path.to_string()))?;
+    let mut response = client.get_range_async(&index.package, index.starting_index, Some(end)).await.map_err(|e| range_error(path, e))?;
+
+    // If the server ignored the Range header, the bytes before the file are discarded
+    let mut skip = if response.status() == StatusCode::PARTIAL_CONTENT { 0 } else { index.starting_index };
+    let mut buffer = Vec::new();
+    while (buffer.len() as u64) < index.file_size {
+        transfer.check(&index.package)?;
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return Err(FilesystemError::UnexpectedEof(path.to_string())),
+            Err(e) => return Err(FilesystemError::FetchError(index.package.clone(), e.to_string(), None)),
+        };
+        let skipped = skip.min(chunk.len() as u64);
+        skip -= skipped;
+        let chunk = &chunk[skipped as usize..];
+        let wanted = (index.file_size - buffer.len() as u64).min(chunk.len() as u64);
+        buffer.extend_from_slice(&chunk[..wanted as usize]);
+        transfer.report(&index.package, buffer.len() as u64, Some(index.file_size));
+    }
+    Ok(buffer)
+}
+
+/// A server responds to a range that starts after the end of the
+/// package with `416 Range Not Satisfiable`
+#[cfg(feature = "aura")]
+fn range_error(path: &str, error: FilesystemError) -> FilesystemError {
+    match error {
+        FilesystemError::FetchError(_, _, Some(416)) => FilesystemError::UnexpectedEof(path.to_string()),
+        error => error,
+    }
+}
+
+pub fn read(path: &str, root: &str, index: &AssetPackIndex) -> FilesystemResult<Vec<u8>> {
+    let mut reader = open(path, root, index)?;
+
     // read `index.file_size` bytes
     let mut buffer = vec![0u8; index.file_size as usize];
-    let bytes_read = package.read(&mut buffer).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))? as u64;
+    reader.read_exact(&mut buffer).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))?;
+
+    Ok(buffer)
+}
+
+#[cfg(feature = "async")]
+pub async fn read_async(path: &str, root: &str, index: &AssetPackIndex) -> FilesystemResult<Vec<u8>> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let package_path = package_path_async(path, root, index).await?;
+    let to_error = |e: io::Error| FilesystemError::from(e).with_path(path.to_string());
+
+    let mut package = tokio::fs::File::open(&package_path).await.map_err(to_error)?;
+    let package_size = package.metadata().await.map_err(to_error)?.len();
 
     // this can happen if the file doesn't have that many bytes.
-    if bytes_read < index.file_size {
+    if index.starting_index.checked_add(index.file_size).is_none_or(|end| end > package_size) {
         return Err(FilesystemError::UnexpectedEof(path.to_string()));
     }
 
+    package.seek(SeekFrom::Start(index.starting_index)).await.map_err(to_error)?;
+
+    let mut buffer = vec![0u8; index.file_size as usize];
+    package.read_exact(&mut buffer).await.map_err(to_error)?;
+
     Ok(buffer)
 }
 
@@ -35,3 +242,93 @@ pub fn read_to_string(path: &str, root: &str, index: &AssetPackIndex) -> Filesys
     let buffer = read(path, root, index)?;
     String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use crate::{config::index::AssetPackIndex, readers::assetpackage::open, FilesystemError, FilesystemResult};
+
+    #[test]
+    fn bounded_reader() -> FilesystemResult<()> {
+        let index = AssetPackIndex { package: "package.oap".to_string(), starting_index: 28, file_size: 28 };
+        let mut reader = open("virtualFolder/vfile1-copy.txt", "tests/assetpackage/", &index)?;
+
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello, world! This is a test");
+
+        reader.seek(SeekFrom::End(-4))?;
+        let mut end = String::new();
+        reader.read_to_string(&mut end)?;
+        assert_eq!(end, "test");
+
+        reader.seek(SeekFrom::Start(7))?;
+        let mut word = [0u8; 5];
+        reader.read_exact(&mut word)?;
+        assert_eq!(&word, b"world");
+        assert_eq!(reader.stream_position()?, 12);
+
+        assert!(reader.seek(SeekFrom::Current(-13)).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn read_without_blocking() -> FilesystemResult<()> {
+        let index = AssetPackIndex { package: "package.oap".to_string(), starting_index: 113, file_size: 13 };
+        let contents = crate::readers::assetpackage::read_async("binaries/example.bin", "tests/assetpackage/", &index).await?;
+        assert_eq!(contents, b"Hello, World!");
+
+        let escaping = AssetPackIndex { package: "../filesystem/testfile.txt".to_string(), starting_index: 0, file_size: 13 };
+        let result = crate::readers::assetpackage::read_async("escaping.txt", "tests/assetpackage/", &escaping).await;
+        assert!(matches!(result, Err(FilesystemError::OutOfBounds(_, _))));
+        Ok(())
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn read_remote_packages() -> FilesystemResult<()> {
+        use crate::{readers::{assetpackage::{open_remote, read_remote}, http::HttpClient, transfer::Transfer}, test_server::TestServer};
+
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let transfer = Transfer::new();
+        for package in [server.url("/package.oap"), server.url("/no-range/package.oap")] {
+            let index = AssetPackIndex { package: package.clone(), starting_index: 28, file_size: 28 };
+            assert_eq!(read_remote("virtualFolder/vfile1-copy.txt", &client, &index, &transfer)?, b"hello, world! This is a test");
+
+            let mut reader = open_remote("virtualFolder/vfile1-copy.txt", &client, &index, &transfer)?;
+            reader.seek(SeekFrom::Start(7))?;
+            let mut word = [0u8; 5];
+            reader.read_exact(&mut word)?;
+            assert_eq!(&word, b"world");
+            let mut end = String::new();
+            reader.read_to_string(&mut end)?;
+            assert_eq!(end, "! This is a test");
+
+            let out_of_package = AssetPackIndex { package, starting_index: 1024, file_size: 28 };
+            assert!(matches!(read_remote("invalid.txt", &client, &out_of_package, &transfer), Err(FilesystemError::UnexpectedEof(_))));
+            assert!(matches!(open_remote("invalid.txt", &client, &out_of_package, &transfer), Err(FilesystemError::UnexpectedEof(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn entry_out_of_package() {
+        let index = AssetPackIndex { package: "package.oap".to_string(), starting_index: 120, file_size: 28 };
+        let result = open("invalid.txt", "tests/assetpackage/", &index);
+        assert!(matches!(result, Err(FilesystemError::UnexpectedEof(_))));
+    }
+
+    #[test]
+    fn package_out_of_bounds() {
+        for package in ["../filesystem/testfile.txt", "../../Cargo.toml"] {
+            let index = AssetPackIndex { package: package.to_string(), starting_index: 0, file_size: 13 };
+            assert!(matches!(open("escaping.txt", "tests/assetpackage/", &index), Err(FilesystemError::OutOfBounds(_, _))));
+        }
+        let absolute = std::fs::canonicalize("tests/filesystem/testfile.txt").unwrap();
+        let index = AssetPackIndex { package: absolute.to_string_lossy().to_string(), starting_index: 0, file_size: 13 };
+        assert!(matches!(open("escaping.txt", "tests/assetpackage/", &index), Err(FilesystemError::OutOfBounds(_, _))));
+    }
+}
@@ -1,44 +1,701 @@
-use reqwest::blocking::Response;
+use std::{fs::File, io::{self, Read, Seek, SeekFrom}};
 
-use crate::FilesystemError;
-use super::FilesystemResult;
+use reqwest::{blocking::Response, header::CONTENT_RANGE, StatusCode};
 
-fn fetch(url: &str) -> FilesystemResult<Response> {
-    match reqwest::blocking::get(url) {
-        Ok(res) => Ok(res),
-        Err(e) => return Err(FilesystemError::FetchError(url.to_string(), e.to_string())),
+use crate::{backend::ReadSeek, FilesystemError, FilesystemOptions};
+use super::{
+    cache::{AuraCache, CacheMetadata},
+    http::HttpClient,
+    integrity::{self, ExpectedHash, VerifyingReader},
+    limits::{LimitedReader, ReadCounter},
+    scheme,
+    transfer::{self, Transfer, TransferReader},
+    FilesystemResult
+};
+
+/// Seeking forward less than this amount of bytes discards the bytes
+/// in between instead of sending a new request.
+const SKIP_THRESHOLD: u64 = 64 * 1024;
+
+/// The body of a web resource, read while it's being downloaded.
+/// 
+/// Seeking backwards (or far forward) sends a new request with a `Range`
+/// header. If the server doesn't support ranges, the resource is downloaded
+/// again from the start and the bytes before the new position are discarded.
+#[derive(Debug)]
+pub struct AuraStream {
+    client: HttpClient,
+    url: String,
+    response: Response,
+    position: u64,
+    /// Total size of the resource, if the server sent it
+    length: Option<u64>
+}
+
+impl AuraStream {
+    /// Starts reading a web resource at a specific byte
+    pub(crate) fn starting_at(client: &HttpClient, url: &str, start: u64) -> FilesystemResult<Self> {
+        let (response, length) = fetch_from(client, url, start)?;
+        Ok(AuraStream { client: client.clone(), url: url.to_string(), response, position: start, length })
+    }
+
+    /// Total size of the resource, if the server sent it
+    pub(crate) fn len(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// Starts reading the body at `target`
+    fn restart_at(&mut self, target: u64) -> io::Result<()> {
+        let (response, length) = fetch_from(&self.client, &self.url, target).map_err(io::Error::other)?;
+        self.length = length.or(self.length);
+        self.response = response;
+        self.position = target;
+        Ok(())
+    }
+}
+
+/// Requests a web resource from `start` onwards. If the server ignores the
+/// `Range` header, the bytes before `start` are discarded.
+/// 
+/// Returns the response, positioned at `start`, and the total size of the
+/// resource if the server sent it.
+fn fetch_from(client: &HttpClient, url: &str, start: u64) -> FilesystemResult<(Response, Option<u64>)> {
+    let mut response = client.get_range(url, start, None)?;
+    if response.status() == StatusCode::PARTIAL_CONTENT {
+        let length = total_from_content_range(&response);
+        return Ok((response, length));
+    }
+
+    // The server ignored the Range header and sent everything
+    let length = response.content_length();
+    io::copy(&mut (&mut response).take(start), &mut io::sink()).map_err(|e| FilesystemError::FetchError(url.to_string(), e.to_string(), None))?;
+    Ok((response, length))
+}
+
+/// Reads the total size of a resource from a `Content-Range: bytes start-end/total` header
+fn total_from_content_range(response: &Response) -> Option<u64> {
+    let header = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    header.rsplit_once('/')?.1.parse().ok()
+}
+
+impl Read for AuraStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.response.read(buf)?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
     }
 }
 
-pub fn read_to_string(url: &str) -> FilesystemResult<String> {
-    let response = fetch(url)?;
+impl Seek for AuraStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => match self.length {
+                Some(length) => length.checked_add_signed(offset),
+                None => return Err(io::Error::new(io::ErrorKind::Unsupported, "the server didn't send the size of the resource")),
+            },
+        };
+        let target = target.ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
 
-    match response.text() {
-        Ok(text) => Ok(text),
-        Err(e) => Err(FilesystemError::FetchError(url.to_string(), e.to_string())),
+        if target >= self.position && target - self.position < SKIP_THRESHOLD {
+            let distance = target - self.position;
+            let skipped = io::copy(&mut (&mut self.response).take(distance), &mut io::sink())?;
+            self.position += skipped;
+        } else if target != self.position {
+            self.restart_at(target)?;
+        }
+        Ok(self.position)
     }
 }
 
-pub fn read(url: &str) -> FilesystemResult<Vec<u8>> {
-    let response = fetch(url)?;
+/// Starts downloading a web resource and returns a stream of its body
+/// that reports its progress, can be cancelled and fails when it goes
+/// over the size limits of `options`.
+fn stream(client: &HttpClient, url: &str, options: &FilesystemOptions, transfer: &Transfer) -> FilesystemResult<LimitedReader<TransferReader<AuraStream>>> {
+    transfer.check(url)?;
+    stream_response(client, url, client.get(url)?, options, transfer)
+}
+
+/// Same as [`stream`], for a response that was already received
+fn stream_response(client: &HttpClient, url: &str, response: Response, options: &FilesystemOptions, transfer: &Transfer) -> FilesystemResult<LimitedReader<TransferReader<AuraStream>>> {
+    let counter = ReadCounter::new(url, options);
+    let length = response.content_length();
+    if let Some(length) = length {
+        counter.check(length)?;
+    }
+    let stream = AuraStream { client: client.clone(), url: url.to_string(), response, position: 0, length };
+    Ok(LimitedReader::new(TransferReader::new(stream, url, transfer, length), counter))
+}
+
+/// Returns the metadata of a cached entry if it has to be checked with the
+/// server before it's used. Entries with a hash never change, and offline
+/// mode never checks entries.
+fn stale_metadata(cache: &AuraCache, key: &str, hash: Option<&str>, options: &FilesystemOptions) -> Option<CacheMetadata> {
+    if hash.is_some() || options.is_offline() {
+        return None;
+    }
+    let metadata = cache.metadata(key).unwrap_or_default();
+    metadata.is_stale(options.revalidation()).then_some(metadata)
+}
+
+/// Asynchronous version of [`stale_metadata`]
+#[cfg(feature = "async")]
+async fn stale_metadata_async(cache: &AuraCache, key: &str, hash: Option<&str>, options: &FilesystemOptions) -> Option<CacheMetadata> {
+    if hash.is_some() || options.is_offline() {
+        return None;
+    }
+    let metadata = cache.metadata_async(key).await.unwrap_or_default();
+    metadata.is_stale(options.revalidation()).then_some(metadata)
+}
+
+/// Wraps a reader in a [`VerifyingReader`] if there's a hash to verify
+fn verifying(reader: impl ReadSeek + 'static, url: &str, hash: Option<&str>) -> FilesystemResult<Box<dyn ReadSeek>> {
+    match hash {
+        Some(hash) => Ok(Box::new(VerifyingReader::new(reader, url, ExpectedHash::parse(url, hash)?))),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+/// Opens a web resource for reading.
+/// 
+/// Without a cache, the resource is read while it's being downloaded. With a
+/// cache, the resource is downloaded into the cache (without loading it into
+/// memory) and the cached file is opened. Cached files are checked for changes
+/// as configured with [`FilesystemOptions::with_revalidation`].
+/// 
+/// If a hash is provided, the stream is verified when it's read to the end
+/// (see [`VerifyingReader`]).
+/// 
+/// URLs with a [`scheme::SchemeHandler`] are opened with it and never cached.
+pub fn open(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<Box<dyn ReadSeek>> {
+    let transfer = options.transfer();
+    transfer.check(url)?;
+    if let Some(handler) = scheme::handler_for(url, options)? {
+        return verifying(LimitedReader::new(handler.open(url)?, ReadCounter::new(url, options)), url, hash);
+    }
+
+    let Some(cache_dir) = options.cache_dir() else {
+        if options.is_offline() {
+            return Err(FilesystemError::Offline(url.to_string()));
+        }
+        return verifying(stream(client, url, options, transfer)?, url, hash);
+    };
+
+    let cache = AuraCache::new(cache_dir);
+    let key = AuraCache::key(url, hash)?;
+    let path = cache.path(&key);
+    let cached = path.exists();
+    let stale = match cached {
+        true => stale_metadata(&cache, &key, hash, options),
+        false => None,
+    };
+    let mut downloaded = false;
+    if !cached || stale.is_some() {
+        if options.is_offline() {
+            return Err(FilesystemError::Offline(url.to_string()));
+        }
+        let response = match &stale {
+            Some(metadata) => client.get_if_changed(url, metadata)?,
+            None => client.get(url)?,
+        };
+        let metadata = CacheMetadata::from_headers(response.headers());
+        match stale {
+            Some(stale) if response.status() == StatusCode::NOT_MODIFIED => {
+                let _ = cache.put_metadata(&key, &stale.revalidated(metadata));
+            },
+            _ => {
+                // The hash is checked while the entry is written, it's never stored if it doesn't match
+                let mut stream = stream_response(client, url, response, options, transfer)?;
+                let result = match hash {
+                    Some(hash) => cache.put_from(&key, &mut VerifyingReader::new(&mut stream, url, ExpectedHash::parse(url, hash)?)),
+                    None => cache.put_from(&key, &mut stream),
+                };
+                if result.is_err() {
+                    stream.refund();
+                }
+                result?;
+                downloaded = true;
+                if hash.is_none() {
+                    let _ = cache.put_metadata(&key, &metadata);
+                }
+            },
+        }
+    }
+
+    let to_error = |e: io::Error| FilesystemError::from(e).with_path(path.to_string_lossy().to_string());
+    let file = File::open(&path).map_err(to_error)?;
+    if downloaded {
+        // The download was already counted
+        return verifying(file, url, hash);
+    }
+    let counter = ReadCounter::new(url, options);
+    counter.check(file.metadata().map_err(to_error)?.len())?;
+    verifying(LimitedReader::new(file, counter), url, hash)
+}
+
+/// Downloads a web resource as a string. If a hash is provided, the
+/// resource is verified before it is decoded.
+/// 
+/// The resource is read like with [`read`] and must be valid UTF-8, whatever
+/// charset the server reports. Invalid text fails with [`FilesystemError::Generic`].
+pub fn read_to_string(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<String> {
+    let buffer = read(client, url, hash, options)?;
+    String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(url.to_string(), e.to_string()))
+}
+
+/// Reads a web resource as binary, from the cache if possible. If a hash
+/// is provided, the resource is verified before it is returned.
+/// 
+/// Cached resources that don't match their hash are downloaded again (or
+/// rejected in offline mode). Cached resources without a hash are checked
+/// for changes as configured with [`FilesystemOptions::with_revalidation`].
+/// 
+/// URLs with a [`scheme::SchemeHandler`] are read with it and never cached.
+pub fn read(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<Vec<u8>> {
+    read_with(client, url, hash, options, &Transfer::default())
+}
+
+/// Same as [`read`], but the download is also reported to the observers
+/// of `transfer` and stopped by its tokens (besides the ones in `options`).
+pub fn read_with(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+    let transfer = options.transfer().merged(transfer);
+    transfer.check(url)?;
+    if let Some(handler) = scheme::handler_for(url, options)? {
+        return read_handler(handler, url, hash, options);
+    }
+
+    let cache = match options.cache_dir() {
+        Some(cache_dir) => Some((AuraCache::new(cache_dir), AuraCache::key(url, hash)?)),
+        None => None,
+    };
+
+    // Cached entries are counted like downloads
+    let mut counter = ReadCounter::new(url, options);
+    // A cached entry that has to be checked before it's used
+    let mut stale = None;
+    if let Some((cache, key)) = &cache {
+        if let Some(binary) = cache.get(key, &counter)? {
+            match integrity::verify(url, hash, &binary) {
+                Ok(()) => match stale_metadata(cache, key, hash, options) {
+                    Some(metadata) => stale = Some((binary, metadata)),
+                    None => return counter.add(binary.len() as u64).map(|()| binary),
+                },
+                Err(e) if options.is_offline() => return Err(e),
+                Err(_) => cache.remove(key),
+            }
+        }
+    }
+    if options.is_offline() {
+        return Err(FilesystemError::Offline(url.to_string()));
+    }
+
+    transfer.check(url)?;
+    let response = match &stale {
+        Some((_, metadata)) => client.get_if_changed(url, metadata)?,
+        None => client.get(url)?,
+    };
+    let metadata = CacheMetadata::from_headers(response.headers());
+    if let (Some((cache, key)), Some((binary, stale))) = (&cache, stale) {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let _ = cache.put_metadata(key, &stale.revalidated(metadata));
+            return counter.add(binary.len() as u64).map(|()| binary);
+        }
+    }
+
+    let binary = download(client, url, response, hash, options, &transfer)?;
+    if let Some((cache, key)) = &cache {
+        // The cache is only an optimization, the file was downloaded
+        // successfully even if it can't be stored
+        let _ = cache.put(key, &binary);
+        if hash.is_none() {
+            let _ = cache.put_metadata(key, &metadata);
+        }
+    }
+    Ok(binary)
+}
+
+/// Reads a URL with its scheme handler. The resource is streamed from
+/// [`scheme::SchemeHandler::open`] and counted like a download.
+fn read_handler(handler: &dyn scheme::SchemeHandler, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<Vec<u8>> {
+    let mut binary = Vec::new();
+    let mut reader = LimitedReader::new(handler.open(url)?, ReadCounter::new(url, options));
+    let result = reader.read_to_end(&mut binary)
+        .map_err(|e| transfer::from_io_error(url, e))
+        .and_then(|_| integrity::verify(url, hash, &binary));
+
+    // Failed reads don't count against the total limit
+    if result.is_err() {
+        reader.refund();
+    }
+    result.map(|()| binary)
+}
+
+/// Reads the body of a response as binary
+fn download(client: &HttpClient, url: &str, response: Response, hash: Option<&str>, options: &FilesystemOptions, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+    let mut binary = Vec::new();
+    let mut stream = stream_response(client, url, response, options, transfer)?;
+    let result = stream.read_to_end(&mut binary)
+        .map_err(|e| transfer::from_io_error(url, e))
+        .and_then(|_| integrity::verify(url, hash, &binary));
+
+    // Failed downloads don't count against the total limit
+    if result.is_err() {
+        stream.refund();
+    }
+    result.map(|()| binary)
+}
+
+/// Asynchronous version of [`read_to_string`]
+#[cfg(feature = "async")]
+pub async fn read_to_string_async(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<String> {
+    let buffer = read_async(client, url, hash, options).await?;
+    String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(url.to_string(), e.to_string()))
+}
+
+/// Asynchronous version of [`read`]
+#[cfg(feature = "async")]
+pub async fn read_async(client: &HttpClient, url: &str, hash: Option<&str>, options: &FilesystemOptions) -> FilesystemResult<Vec<u8>> {
+    let transfer = options.transfer();
+    transfer.check(url)?;
+    // Scheme handlers are synchronous
+    if let Some(handler) = scheme::handler_for(url, options)? {
+        return read_handler(handler, url, hash, options);
+    }
+
+    let cache = match options.cache_dir() {
+        Some(cache_dir) => Some((AuraCache::new(cache_dir), AuraCache::key(url, hash)?)),
+        None => None,
+    };
 
-    match response.bytes() {
-        Ok(binary) => Ok(binary.into()),
-        Err(e) => Err(FilesystemError::FetchError(url.to_string(), e.to_string())),
+    let mut counter = ReadCounter::new(url, options);
+    let mut stale = None;
+    if let Some((cache, key)) = &cache {
+        if let Some(binary) = cache.get_async(key, &counter).await? {
+            match integrity::verify(url, hash, &binary) {
+                Ok(()) => match stale_metadata_async(cache, key, hash, options).await {
+                    Some(metadata) => stale = Some((binary, metadata)),
+                    None => return counter.add(binary.len() as u64).map(|()| binary),
+                },
+                Err(e) if options.is_offline() => return Err(e),
+                Err(_) => cache.remove_async(key).await,
+            }
+        }
+    }
+    if options.is_offline() {
+        return Err(FilesystemError::Offline(url.to_string()));
+    }
+
+    let mut response = match &stale {
+        Some((_, metadata)) => client.get_if_changed_async(url, metadata).await?,
+        None => client.get_async(url).await?,
+    };
+    let metadata = CacheMetadata::from_headers(response.headers());
+    if let (Some((cache, key)), Some((binary, stale))) = (&cache, stale) {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let _ = cache.put_metadata_async(key, &stale.revalidated(metadata)).await;
+            return counter.add(binary.len() as u64).map(|()| binary);
+        }
+    }
+    let total = response.content_length();
+    if let Some(total) = total {
+        counter.check(total)?;
     }
+    let mut binary = Vec::new();
+    let result = async {
+        loop {
+            transfer.check(url)?;
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    counter.add(chunk.len() as u64)?;
+                    binary.extend_from_slice(&chunk);
+                },
+                Ok(None) => break,
+                Err(e) => return Err(FilesystemError::FetchError(url.to_string(), e.to_string(), None)),
+            }
+            transfer.report(url, binary.len() as u64, total);
+        }
+        integrity::verify(url, hash, &binary)
+    }.await;
+    // Failed downloads don't count against the total limit
+    counter.settle(result)?;
+
+    if let Some((cache, key)) = &cache {
+        // The cache is only an optimization, the file was downloaded
+        // successfully even if it can't be stored
+        let _ = cache.put_async(key, &binary).await;
+        if hash.is_none() {
+            let _ = cache.put_metadata_async(key, &metadata).await;
+        }
+    }
+    Ok(binary)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::readers::aura::{read, read_to_string};
+    use std::{fs, io::{Read, Seek, SeekFrom}, time::Duration};
+
+    use crate::{
+        readers::{aura::{open, read, read_to_string}, cache::{AuraCache, CacheMetadata}, http::HttpClient, limits::ReadCounter},
+        test_server::{self, TestServer},
+        FilesystemError, FilesystemOptions, FilesystemResult, HttpOptions, Revalidation
+    };
+
+    const BINARY_HASH: &str = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
 
     #[test]
-    fn fetch_from_pastebin() {
-        let result = read_to_string("https://pastebin.com/raw/t0qjYDWt").unwrap();
-        assert_eq!(result, "Hello, if you fetched this file from an Aura file, that means that ORO Filesystem is working!!");
+    fn fetch_from_local_server() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let options = FilesystemOptions::new();
 
-        let result_bin = read("https://pastebin.com/raw/eQe9aqfZ").unwrap();
-        let expected = vec![0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x57, 0x6F, 0x72, 0x6C, 0x64, 0x21];
-        assert_eq!(result_bin, expected);
+        assert_eq!(read_to_string(&client, &server.url("/raw/t0qjYDWt"), None, &options)?, test_server::TEXT);
+        assert_eq!(read(&client, &server.url("/raw/eQe9aqfZ"), None, &options)?, test_server::BINARY);
+        // Text is decoded strictly, whatever the options are
+        assert!(matches!(read_to_string(&client, &server.url("/large.bin"), None, &options), Err(FilesystemError::Generic(..))));
+
+        let mut contents = String::new();
+        open(&client, &server.url("/raw/t0qjYDWt"), None, &options)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, test_server::TEXT);
+        Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn revalidate_cached_files() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("revalidation");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let cache = AuraCache::new(&cache_dir);
+        let options = FilesystemOptions::new().with_cache_dir(&cache_dir);
+        let always = options.clone().with_revalidation(Revalidation::Always);
+        let url = server.url("/versioned/v1");
+        let key = AuraCache::key(&url, None)?;
+
+        assert_eq!(read(&client, &url, None, &always)?, b"Version v1");
+        assert_eq!(cache.metadata(&key).and_then(|metadata| metadata.etag).as_deref(), Some("\"v1\""));
+        // 304 Not Modified, the cached file is used
+        assert_eq!(read(&client, &url, None, &always)?, b"Version v1");
+        let mut contents = Vec::new();
+        open(&client, &url, None, &always)?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"Version v1");
+        assert_eq!(server.hits("/versioned/v1"), 3);
+
+        // Files that changed are downloaded again
+        cache.put(&key, b"Version v0")?;
+        cache.put_metadata(&key, &CacheMetadata { etag: Some(String::from("\"v0\"")), ..CacheMetadata::default() })?;
+        assert_eq!(read(&client, &url, None, &always)?, b"Version v1");
+        assert_eq!(cache.get(&key, &ReadCounter::new(&url, &options))?.as_deref(), Some(&b"Version v1"[..]));
+
+        // Without an ETag, the Last-Modified date is used
+        cache.put_metadata(&key, &CacheMetadata { last_modified: Some(test_server::LAST_MODIFIED.to_string()), ..CacheMetadata::default() })?;
+        cache.put(&key, b"Not modified")?;
+        assert_eq!(read(&client, &url, None, &always)?, b"Not modified");
+        assert_eq!(server.hits("/versioned/v1"), 5);
+
+        // Never, before the TTL or offline, the cached file is used without asking the server
+        assert_eq!(read(&client, &url, None, &options)?, b"Not modified");
+        let ttl = options.clone().with_revalidation(Revalidation::After(Duration::from_secs(3600)));
+        assert_eq!(read(&client, &url, None, &ttl)?, b"Not modified");
+        assert_eq!(read(&client, &url, None, &always.clone().with_offline_mode(true))?, b"Not modified");
+        assert_eq!(server.hits("/versioned/v1"), 5);
+
+        // After the TTL (or without metadata), it's checked
+        cache.remove(&key);
+        cache.put(&key, b"Version v0")?;
+        assert_eq!(read(&client, &url, None, &ttl)?, b"Version v1");
+        assert_eq!(server.hits("/versioned/v1"), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn status_codes() {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let options = FilesystemOptions::new();
+        let status = |code: u16| read_to_string(&client, &server.url(&format!("/status/{code}")), None, &options);
+
+        assert!(matches!(status(404), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(status(410), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(status(401), Err(FilesystemError::PermissionDenied(_))));
+        assert!(matches!(status(403), Err(FilesystemError::PermissionDenied(_))));
+        assert!(matches!(status(500), Err(FilesystemError::FetchError(_, _, Some(500)))));
+        assert!(matches!(status(418), Err(FilesystemError::FetchError(_, _, Some(418)))));
+        assert!(matches!(read(&client, &server.url("/invented.txt"), None, &options), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(open(&client, &server.url("/status/403"), None, &options), Err(FilesystemError::PermissionDenied(_))));
+    }
+
+    #[test]
+    fn large_bodies() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let options = FilesystemOptions::new();
+        let expected = test_server::large_body();
+
+        assert_eq!(read(&client, &server.url("/large.bin"), None, &options)?, expected);
+
+        // Seeking backwards and far forward sends new requests with a Range header
+        let mut stream = open(&client, &server.url("/large.bin"), None, &options)?;
+        let mut buffer = [0u8; 16];
+        stream.seek(SeekFrom::Start(3 * 1024 * 1024))?;
+        stream.read_exact(&mut buffer)?;
+        assert_eq!(&buffer[..], &expected[3 * 1024 * 1024..3 * 1024 * 1024 + 16]);
+        stream.seek(SeekFrom::Start(100))?;
+        stream.read_exact(&mut buffer)?;
+        assert_eq!(&buffer[..], &expected[100..116]);
+        stream.seek(SeekFrom::End(-16))?;
+        stream.read_exact(&mut buffer)?;
+        assert_eq!(&buffer[..], &expected[test_server::LARGE_SIZE - 16..]);
+        assert_eq!(server.hits("/large.bin"), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn slow_responses() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let options = FilesystemOptions::new();
+
+        let impatient = HttpClient::new(&HttpOptions::new().with_timeout(Duration::from_millis(100)));
+        assert!(matches!(read(&impatient, &server.url("/slow.txt"), None, &options), Err(FilesystemError::FetchError(_, _, None))));
+
+        let patient = HttpClient::new(&HttpOptions::new().with_timeout(Duration::from_secs(10)));
+        assert_eq!(read_to_string(&patient, &server.url("/slow.txt"), None, &options)?, "Sorry for the wait");
+        Ok(())
+    }
+
+    #[test]
+    fn check_hashes() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let options = FilesystemOptions::new();
+        let url = server.url("/raw/eQe9aqfZ");
+        let wrong_hash = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+        assert_eq!(read(&client, &url, Some(BINARY_HASH), &options)?, test_server::BINARY);
+        assert_eq!(read_to_string(&client, &url, Some(BINARY_HASH), &options)?, "Hello, World!");
+        assert!(matches!(read(&client, &url, Some(wrong_hash), &options), Err(FilesystemError::HashMismatch(_, _, _))));
+        assert!(read(&client, &url, Some("md5:1234"), &options).is_err());
+
+        // Streams are checked when they're read to the end
+        let mut contents = Vec::new();
+        open(&client, &url, Some(BINARY_HASH), &options)?.read_to_end(&mut contents)?;
+        assert_eq!(contents, test_server::BINARY);
+        assert!(open(&client, &url, Some(wrong_hash), &options)?.read_to_end(&mut Vec::new()).is_err());
+
+        // Corrupted cache entries are downloaded again
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("hash-cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let cached = FilesystemOptions::new().with_cache_dir(&cache_dir);
+        let cache = AuraCache::new(&cache_dir);
+        cache.put(&AuraCache::key(&url, Some(BINARY_HASH))?, b"Hello, World?")?;
+        assert_eq!(read(&client, &url, Some(BINARY_HASH), &cached)?, test_server::BINARY);
+        assert_eq!(cache.get(&AuraCache::key(&url, Some(BINARY_HASH))?, &ReadCounter::new(&url, &cached))?, Some(test_server::BINARY.to_vec()));
+
+        // Downloads that don't match are never cached
+        assert!(open(&client, &url, Some(wrong_hash), &cached).is_err());
+        assert_eq!(cache.get(&AuraCache::key(&url, Some(wrong_hash))?, &ReadCounter::new(&url, &cached))?, None);
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn fetch_from_local_server_async() -> FilesystemResult<()> {
+        use crate::readers::aura::{read_async, read_to_string_async};
+
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        let options = FilesystemOptions::new();
+
+        assert_eq!(read_to_string_async(&client, &server.url("/raw/t0qjYDWt"), None, &options).await?, test_server::TEXT);
+        assert!(matches!(read_to_string_async(&client, &server.url("/large.bin"), None, &options).await, Err(FilesystemError::Generic(..))));
+        assert_eq!(read_async(&client, &server.url("/raw/eQe9aqfZ"), Some(BINARY_HASH), &options).await?, test_server::BINARY);
+        assert!(matches!(read_async(&client, &server.url("/status/404"), None, &options).await, Err(FilesystemError::NotFound(_))));
+        assert_eq!(read_async(&client, &server.url("/large.bin"), None, &options).await?, test_server::large_body());
+
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("revalidation-async");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let always = options.with_cache_dir(&cache_dir).with_revalidation(Revalidation::Always);
+        AuraCache::new(&cache_dir).put(&AuraCache::key(&server.url("/versioned/v2"), None)?, b"Version v1")?;
+        assert_eq!(read_async(&client, &server.url("/versioned/v2"), None, &always).await?, b"Version v2");
+        assert_eq!(read_async(&client, &server.url("/versioned/v2"), None, &always).await?, b"Version v2");
+        assert_eq!(server.hits("/versioned/v2"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn read_offline_from_cache() -> FilesystemResult<()> {
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("offline-cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let options = FilesystemOptions::new().with_cache_dir(&cache_dir).with_offline_mode(true);
+        let client = HttpClient::default();
+
+        let url = "https://example.com/cached.txt";
+        let hashed_url = "https://example.com/hashed.txt";
+        let hash = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+
+        // Nothing is cached yet
+        assert!(matches!(read(&client, url, None, &options), Err(FilesystemError::Offline(_))));
+        assert!(matches!(open(&client, url, None, &options), Err(FilesystemError::Offline(_))));
+        assert!(matches!(read(&client, url, None, &FilesystemOptions::new().with_offline_mode(true)), Err(FilesystemError::Offline(_))));
+
+        let cache = AuraCache::new(&cache_dir);
+        cache.put(&AuraCache::key(url, None)?, b"Cached by URL")?;
+        cache.put(&AuraCache::key(hashed_url, Some(hash))?, b"Hello, World!")?;
+
+        assert_eq!(read_to_string(&client, url, None, &options)?, "Cached by URL");
+        assert_eq!(read(&client, hashed_url, Some(hash), &options)?, b"Hello, World!");
+
+        let mut contents = String::new();
+        open(&client, hashed_url, Some(hash), &options)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hello, World!");
+
+        // Corrupted entries are never served
+        cache.put(&AuraCache::key(hashed_url, Some(hash))?, b"Hello, World?")?;
+        assert!(matches!(read(&client, hashed_url, Some(hash), &options), Err(FilesystemError::HashMismatch(_, _, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn limit_cache_hits() -> FilesystemResult<()> {
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("limited-cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let options = FilesystemOptions::new().with_cache_dir(&cache_dir).with_offline_mode(true).with_max_total_size(20);
+        let client = HttpClient::default();
+
+        let url = "https://example.com/hashed.txt";
+        AuraCache::new(&cache_dir).put(&AuraCache::key(url, Some(BINARY_HASH))?, test_server::BINARY)?;
+
+        // Cached files are counted like downloads
+        assert_eq!(read(&client, url, Some(BINARY_HASH), &options)?, test_server::BINARY);
+        assert_eq!(options.total_read(), 13);
+        assert!(matches!(read(&client, url, Some(BINARY_HASH), &options), Err(FilesystemError::SizeLimitExceeded(_, 20))));
+        assert!(matches!(open(&client, url, Some(BINARY_HASH), &options), Err(FilesystemError::SizeLimitExceeded(_, 20))));
+        assert_eq!(options.total_read(), 13);
+
+        // Their size is checked before they're read
+        let small = options.clone().with_max_read_size(5);
+        small.reset_total_read();
+        assert!(matches!(read(&client, url, Some(BINARY_HASH), &small), Err(FilesystemError::SizeLimitExceeded(_, 5))));
+        assert!(matches!(open(&client, url, Some(BINARY_HASH), &small), Err(FilesystemError::SizeLimitExceeded(_, 5))));
+        assert_eq!(small.total_read(), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn read_offline_from_cache_async() -> FilesystemResult<()> {
+        use crate::readers::aura::read_to_string_async;
+
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("offline-cache-async");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let options = FilesystemOptions::new().with_cache_dir(&cache_dir).with_offline_mode(true);
+        let client = HttpClient::default();
+
+        let url = "https://example.com/cached.txt";
+        assert!(matches!(read_to_string_async(&client, url, None, &options).await, Err(FilesystemError::Offline(_))));
+
+        AuraCache::new(&cache_dir).put(&AuraCache::key(url, None)?, b"Cached by URL")?;
+        assert_eq!(read_to_string_async(&client, url, None, &options).await?, "Cached by URL");
+        Ok(())
+    }
+}
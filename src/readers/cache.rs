@@ -0,0 +1,342 @@
+//! On-disk cache for Aura files.
+//! 
+//! Every file is stored in the cache directory with a name derived from
+//! its hash (`sha256-<hex digest>`) if the index has one, or from the
+//! SHA-256 of its URL (`url-<hex digest>`) if it doesn't. Files with
+//! a hash are shared between every URL that serves the same content.
+//! 
+//! Files without a hash also have a [`CacheMetadata`] file next to them
+//! (`url-<hex digest>.json`), used to check whether they changed.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+
+use crate::{FilesystemError, Revalidation};
+
+use super::{integrity::{self, ExpectedHash}, limits::ReadCounter, FilesystemResult};
+
+/// Number of temporary files created by this process, so concurrent
+/// writers of the same entry never share one
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// A cache directory
+#[derive(Debug, Clone)]
+pub struct AuraCache {
+    dir: PathBuf
+}
+
+impl AuraCache {
+    pub fn new(dir: &Path) -> Self {
+        AuraCache { dir: dir.to_path_buf() }
+    }
+
+    /// Returns the name of the cache entry of a file
+    pub fn key(url: &str, hash: Option<&str>) -> FilesystemResult<String> {
+        match hash {
+            Some(hash) => {
+                // Parsed so equivalent hashes (like uppercase digests) share an entry
+                ExpectedHash::parse(url, hash)?;
+                Ok(hash.to_ascii_lowercase().replacen(':', "-", 1))
+            },
+            None => Ok(integrity::sha256(url.as_bytes()).replacen("sha256:", "url-", 1)),
+        }
+    }
+
+    /// Returns the path of a cache entry
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    /// Returns a new path for the temporary file of a cache entry. Every call
+    /// returns a different path, even in other processes that share the cache.
+    fn temporary_path(&self, key: &str) -> PathBuf {
+        let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!("{key}.{}-{count}.part", process::id()))
+    }
+
+    /// Reads a cache entry. Returns [`None`] if it doesn't exist.
+    /// 
+    /// The size of the entry is checked against the limits of `counter` before
+    /// it's read, but it isn't counted: that's up to the caller.
+    pub fn get(&self, key: &str, counter: &ReadCounter) -> FilesystemResult<Option<Vec<u8>>> {
+        let path = self.path(key);
+        let to_error = |e: io::Error| FilesystemError::from(e).with_path(path.to_string_lossy().to_string());
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(to_error(e)),
+        };
+        let size = file.metadata().map_err(to_error)?.len();
+        counter.check(size)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(to_error)?;
+        Ok(Some(data))
+    }
+
+    /// Writes a cache entry, replacing it if it already exists
+    pub fn put(&self, key: &str, data: &[u8]) -> FilesystemResult<()> {
+        self.put_from(key, &mut io::Cursor::new(data))?;
+        Ok(())
+    }
+
+    /// Writes a cache entry from a reader and returns the size of the entry.
+    /// 
+    /// The data is written to a temporary file first, so a download that fails
+    /// halfway never leaves a truncated entry in the cache. Concurrent writers
+    /// of the same entry use different temporary files and the last one to
+    /// finish replaces the entry.
+    pub fn put_from(&self, key: &str, reader: &mut dyn Read) -> FilesystemResult<u64> {
+        let path = self.path(key);
+        let temporary_path = self.temporary_path(key);
+        let path_string = |p: &Path| p.to_string_lossy().to_string();
+
+        fs::create_dir_all(&self.dir).map_err(|e| FilesystemError::from(e).with_path(path_string(&self.dir)))?;
+
+        let mut file = File::create(&temporary_path).map_err(|e| FilesystemError::from(e).with_path(path_string(&temporary_path)))?;
+        let size = match io::copy(reader, &mut file) {
+            Ok(size) => size,
+            Err(e) => {
+                drop(file);
+                let _ = fs::remove_file(&temporary_path);
+                // Errors produced by the reader (like hash mismatches) are kept as they are
+                return Err(match e.into_inner().map(|inner| inner.downcast::<FilesystemError>()) {
+                    Some(Ok(error)) => *error,
+                    Some(Err(inner)) => FilesystemError::Generic(path_string(&path), inner.to_string()),
+                    None => FilesystemError::Generic(path_string(&path), String::from("Couldn't write cache entry")),
+                });
+            },
+        };
+        drop(file);
+
+        fs::rename(&temporary_path, &path).map_err(|e| {
+            let _ = fs::remove_file(&temporary_path);
+            FilesystemError::from(e).with_path(path_string(&path))
+        })?;
+        Ok(size)
+    }
+
+    /// Asynchronous version of [`AuraCache::get`]
+    #[cfg(feature = "async")]
+    pub async fn get_async(&self, key: &str, counter: &ReadCounter) -> FilesystemResult<Option<Vec<u8>>> {
+        use tokio::io::AsyncReadExt;
+
+        let path = self.path(key);
+        let to_error = |e: io::Error| FilesystemError::from(e).with_path(path.to_string_lossy().to_string());
+        let mut file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(to_error(e)),
+        };
+        let size = file.metadata().await.map_err(to_error)?.len();
+        counter.check(size)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await.map_err(to_error)?;
+        Ok(Some(data))
+    }
+
+    /// Asynchronous version of [`AuraCache::put`]
+    #[cfg(feature = "async")]
+    pub async fn put_async(&self, key: &str, data: &[u8]) -> FilesystemResult<()> {
+        let path = self.path(key);
+        let temporary_path = self.temporary_path(key);
+        let to_error = |e: io::Error| FilesystemError::from(e).with_path(path.to_string_lossy().to_string());
+
+        tokio::fs::create_dir_all(&self.dir).await.map_err(to_error)?;
+        let written = match tokio::fs::write(&temporary_path, data).await {
+            Ok(()) => tokio::fs::rename(&temporary_path, &path).await,
+            Err(e) => Err(e),
+        };
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+        }
+        written.map_err(to_error)
+    }
+
+    /// Removes a cache entry (and its metadata) if it exists
+    pub fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+        let _ = fs::remove_file(self.metadata_path(key));
+    }
+
+    /// Asynchronous version of [`AuraCache::remove`]
+    #[cfg(feature = "async")]
+    pub async fn remove_async(&self, key: &str) {
+        let _ = tokio::fs::remove_file(self.path(key)).await;
+        let _ = tokio::fs::remove_file(self.metadata_path(key)).await;
+    }
+
+    /// Returns the path of the metadata of a cache entry
+    fn metadata_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// Reads the metadata of a cache entry. Returns [`None`] if it doesn't
+    /// exist or can't be read, so the entry is treated as never validated.
+    pub fn metadata(&self, key: &str) -> Option<CacheMetadata> {
+        let data = fs::read(self.metadata_path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Writes the metadata of a cache entry, replacing it if it already exists
+    pub fn put_metadata(&self, key: &str, metadata: &CacheMetadata) -> FilesystemResult<()> {
+        let data = self.serialize_metadata(key, metadata)?;
+        self.put(&format!("{key}.json"), &data)
+    }
+
+    fn serialize_metadata(&self, key: &str, metadata: &CacheMetadata) -> FilesystemResult<Vec<u8>> {
+        serde_json::to_vec(metadata).map_err(|e| FilesystemError::Generic(self.metadata_path(key).to_string_lossy().to_string(), e.to_string()))
+    }
+
+    /// Asynchronous version of [`AuraCache::metadata`]
+    #[cfg(feature = "async")]
+    pub async fn metadata_async(&self, key: &str) -> Option<CacheMetadata> {
+        let data = tokio::fs::read(self.metadata_path(key)).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Asynchronous version of [`AuraCache::put_metadata`]
+    #[cfg(feature = "async")]
+    pub async fn put_metadata_async(&self, key: &str, metadata: &CacheMetadata) -> FilesystemResult<()> {
+        let data = self.serialize_metadata(key, metadata)?;
+        self.put_async(&format!("{key}.json"), &data).await
+    }
+}
+
+/// What the server sent with a cached file, used to ask it whether the
+/// file changed with a conditional request
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    /// `ETag` header of the response, sent back as `If-None-Match`
+    #[serde(default)]
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response, sent back as `If-Modified-Since`
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// When the file was downloaded or last revalidated, in seconds since the Unix epoch
+    #[serde(default)]
+    pub validated: u64
+}
+
+impl CacheMetadata {
+    /// Reads the metadata of a response that was just received
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        CacheMetadata {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            validated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+        }
+    }
+
+    /// Updates the metadata with a `304 Not Modified` response, which
+    /// may have new validators
+    pub fn revalidated(self, response: CacheMetadata) -> Self {
+        CacheMetadata {
+            etag: response.etag.or(self.etag),
+            last_modified: response.last_modified.or(self.last_modified),
+            validated: response.validated
+        }
+    }
+
+    /// Returns `true` if the file has to be checked with the server before it's used
+    pub fn is_stale(&self, revalidation: Revalidation) -> bool {
+        match revalidation {
+            Revalidation::Never => false,
+            Revalidation::Always => true,
+            Revalidation::After(ttl) => {
+                let validated = UNIX_EPOCH + Duration::from_secs(self.validated);
+                SystemTime::now().duration_since(validated).unwrap_or_default() >= ttl
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue, ETAG};
+
+    use crate::{readers::{cache::{AuraCache, CacheMetadata}, limits::ReadCounter}, FilesystemOptions, FilesystemResult, Revalidation};
+
+    #[test]
+    fn cache_keys() {
+        let hash = "sha256:DFFD6021BB2BD5B0AF676290809EC3A53191DD81C7F70A4B28688A362182986F";
+        assert_eq!(
+            AuraCache::key("https://example.com/a", Some(hash)).unwrap(),
+            "sha256-dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f"
+        );
+        // Same content, same entry
+        assert_eq!(
+            AuraCache::key("https://example.com/a", Some(hash)).unwrap(),
+            AuraCache::key("https://example.com/b", Some(hash)).unwrap()
+        );
+        // Different URLs without a hash, different entries
+        assert_ne!(
+            AuraCache::key("https://example.com/a", None).unwrap(),
+            AuraCache::key("https://example.com/b", None).unwrap()
+        );
+        assert!(AuraCache::key("https://example.com/a", None).unwrap().starts_with("url-"));
+    }
+
+    #[test]
+    fn store_metadata() -> FilesystemResult<()> {
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("cache-metadata");
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let cache = AuraCache::new(&cache_dir);
+        let key = AuraCache::key("https://example.com/a", None)?;
+        assert_eq!(cache.metadata(&key), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let metadata = CacheMetadata::from_headers(&headers);
+        cache.put(&key, b"a")?;
+        cache.put_metadata(&key, &metadata)?;
+        assert_eq!(cache.metadata(&key).as_ref(), Some(&metadata));
+        assert_eq!(metadata.etag.as_deref(), Some("\"v1\""));
+
+        // A 304 without validators keeps the old ones
+        let revalidated = metadata.clone().revalidated(CacheMetadata::from_headers(&HeaderMap::new()));
+        assert_eq!(revalidated.etag.as_deref(), Some("\"v1\""));
+
+        assert!(!metadata.is_stale(Revalidation::Never));
+        assert!(metadata.is_stale(Revalidation::Always));
+        assert!(!metadata.is_stale(Revalidation::After(Duration::from_secs(3600))));
+        assert!(CacheMetadata::default().is_stale(Revalidation::After(Duration::from_secs(3600))));
+
+        cache.remove(&key);
+        assert_eq!(cache.metadata(&key), None);
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_writers() -> FilesystemResult<()> {
+        let cache_dir = std::env::temp_dir().join("oro-filesystem-tests").join("concurrent-cache");
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let cache = AuraCache::new(&cache_dir);
+
+        std::thread::scope(|scope| {
+            for writer in 0..8u8 {
+                let cache = &cache;
+                scope.spawn(move || cache.put("shared", &[writer; 64 * 1024]).unwrap());
+            }
+        });
+
+        // The entry is complete and written by only one of them
+        let entry = cache.get("shared", &ReadCounter::new("shared", &FilesystemOptions::new()))?.unwrap();
+        assert_eq!(entry.len(), 64 * 1024);
+        assert!(entry.iter().all(|byte| *byte == entry[0]));
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 1);
+        Ok(())
+    }
+}
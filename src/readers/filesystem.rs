@@ -1,20 +1,152 @@
-use std::fs;
+use std::{fs::{self, File}, path::{Path, PathBuf}};
 
-use crate::FilesystemError;
+use crate::{backend::{Backend, BackendKind, DirEntry, Metadata, ReadSeek}, config::{secure_path::BoundChecker, virtual_path}, FilesystemError, FilesystemType};
+#[cfg(feature = "async")]
+use crate::backend::BoxFuture;
 
 use super::FilesystemResult;
 
-pub fn read_to_string(path: &str) -> FilesystemResult<String> {
-    fs::read_to_string(path).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))
+/// Converts a path into a String for errors
+fn path_to_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
 }
 
-pub fn read(path: &str) -> FilesystemResult<Vec<u8>> {
-    fs::read(path).map_err(|e| FilesystemError::from(e).with_path(path.to_string()))
+pub fn read_to_string(path: impl AsRef<Path>) -> FilesystemResult<String> {
+    let path = path.as_ref();
+    fs::read_to_string(path).map_err(|e| FilesystemError::from(e).with_path(path_to_string(path)))
+}
+
+pub fn read(path: impl AsRef<Path>) -> FilesystemResult<Vec<u8>> {
+    let path = path.as_ref();
+    fs::read(path).map_err(|e| FilesystemError::from(e).with_path(path_to_string(path)))
+}
+
+#[cfg(feature = "async")]
+pub async fn read_to_string_async(path: impl AsRef<Path>) -> FilesystemResult<String> {
+    let path = path.as_ref();
+    tokio::fs::read_to_string(path).await.map_err(|e| FilesystemError::from(e).with_path(path_to_string(path)))
+}
+
+#[cfg(feature = "async")]
+pub async fn read_async(path: impl AsRef<Path>) -> FilesystemResult<Vec<u8>> {
+    let path = path.as_ref();
+    tokio::fs::read(path).await.map_err(|e| FilesystemError::from(e).with_path(path_to_string(path)))
+}
+
+/// [`Backend`] for unindexed filesystems. Virtual paths are
+/// directly translated to paths in the native filesystem.
+/// 
+/// Paths that would escape the root (with `..` or symlinks) can't be
+/// read, they return [`FilesystemError::OutOfBounds`].
+#[derive(Debug, Clone)]
+pub struct NativeBackend {
+    root: String,
+    bound_checker: BoundChecker
+}
+
+impl NativeBackend {
+    /// Constructs a backend that reads from the specified root.
+    /// The root must end with `/`.
+    /// 
+    /// This fails if the root doesn't exist.
+    pub fn new(root: &str) -> FilesystemResult<Self> {
+        Ok(NativeBackend { root: root.to_string(), bound_checker: BoundChecker::new(Path::new(root))? })
+    }
+
+    /// Normalizes the virtual path, concatenates the root to it and checks
+    /// that the resulting path is inside the root.
+    /// 
+    /// Returns the canonical path that was checked, which is the one that
+    /// has to be read: the original path could point somewhere else if a
+    /// symlink in it changes.
+    fn to_path(&self, path: &str) -> FilesystemResult<PathBuf> {
+        let out_of_bounds = |e| match e {
+            FilesystemError::OutOfBounds(_, _) => FilesystemError::OutOfBounds(path.to_string(), self.root.clone()),
+            e => e,
+        };
+
+        let real_path = self.root.clone() + &virtual_path::normalize(path).map_err(out_of_bounds)?;
+        self.bound_checker
+            .ensure_in_bounds(Path::new(&real_path))
+            .map_err(out_of_bounds)
+    }
+
+    /// Asynchronous version of [`NativeBackend::to_path`]. Checking the bounds
+    /// reads the filesystem, so it runs in a blocking thread.
+    #[cfg(feature = "async")]
+    async fn to_path_async(&self, path: &str) -> FilesystemResult<PathBuf> {
+        let backend = self.clone();
+        let virtual_path = path.to_string();
+        tokio::task::spawn_blocking(move || backend.to_path(&virtual_path))
+            .await
+            .map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))?
+    }
+}
+
+impl Backend for NativeBackend {
+    fn read(&self, path: &str) -> FilesystemResult<Vec<u8>> {
+        read(&self.to_path(path)?)
+    }
+
+    fn read_to_string(&self, path: &str) -> FilesystemResult<String> {
+        read_to_string(&self.to_path(path)?)
+    }
+
+    #[cfg(feature = "async")]
+    fn read_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<Vec<u8>>> {
+        Box::pin(async move { read_async(self.to_path_async(path).await?).await })
+    }
+
+    #[cfg(feature = "async")]
+    fn read_to_string_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<String>> {
+        Box::pin(async move { read_to_string_async(self.to_path_async(path).await?).await })
+    }
+
+    fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        let real_path = self.to_path(path)?;
+        let file = File::open(&real_path).map_err(|e| FilesystemError::from(e).with_path(path_to_string(&real_path)))?;
+        Ok(Box::new(file))
+    }
+
+    fn metadata(&self, path: &str) -> FilesystemResult<Metadata> {
+        let real_path = self.to_path(path)?;
+        let metadata = fs::metadata(&real_path).map_err(|e| FilesystemError::from(e).with_path(path_to_string(&real_path)))?;
+        Ok(Metadata {
+            size: if metadata.is_dir() { None } else { Some(metadata.len()) },
+            is_dir: metadata.is_dir(),
+            kind: BackendKind::Native,
+            ..Default::default()
+        })
+    }
+
+    fn read_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>> {
+        let real_path = self.to_path(path)?;
+        let content = fs::read_dir(&real_path).map_err(|e| FilesystemError::from(e).with_path(path_to_string(&real_path)))?;
+
+        let directory = virtual_path::normalize(path)?;
+        let mut entries = Vec::new();
+        for file in content {
+            let entry = match file {
+                Ok(e) => e,
+                Err(_) => continue,
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            let path = if directory.is_empty() { name.clone() } else { format!("{directory}/{name}") };
+            entries.push(DirEntry { path, name, is_dir });
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn fs_type(&self) -> FilesystemType {
+        FilesystemType::Filesystem
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::readers::filesystem::{read, read_to_string};
+    use crate::{backend::Backend, readers::filesystem::{read, read_to_string, NativeBackend}, FilesystemError, FilesystemResult};
 
     #[test]
     fn read_string() {
@@ -28,4 +160,32 @@ mod tests {
 
         assert_eq!(contents, expected);
     }
-}
\ No newline at end of file
+    #[test]
+    fn list_directory() -> FilesystemResult<()> {
+        let backend = NativeBackend::new("tests/")?;
+        let entries = backend.read_dir("filesystem")?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "filesystem/testfile.txt");
+        assert_eq!(entries[0].name, "testfile.txt");
+        assert!(!entries[0].is_dir);
+
+        assert_eq!(backend.metadata("filesystem/testfile.txt")?.size, Some(13));
+        assert!(backend.metadata("filesystem")?.is_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn read_out_of_bounds() -> FilesystemResult<()> {
+        let backend = NativeBackend::new("tests/filesystem/")?;
+        assert_eq!(backend.read_to_string("testfile.txt")?, "Hello, World!");
+        assert_eq!(backend.read_to_string("/testfile.txt")?, "Hello, World!");
+        assert_eq!(backend.read_to_string(".//testfile.txt")?, "Hello, World!");
+
+        assert!(matches!(backend.read_to_string("../testfile.txt"), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(backend.read("../../../../../../../../etc/passwd"), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(backend.open("invented/../../testfile.txt"), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(backend.read_dir(".."), Err(FilesystemError::OutOfBounds(_, _))));
+        assert!(matches!(backend.read("invented.txt"), Err(FilesystemError::NotFound(_))));
+        Ok(())
+    }
+}
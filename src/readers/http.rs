@@ -0,0 +1,330 @@
+use std::{sync::{Arc, OnceLock}, thread, time::Duration};
+
+use reqwest::{header::{HeaderName, AUTHORIZATION, IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE}, StatusCode, Url};
+
+use crate::{config::options::HttpOptions, FilesystemError};
+use super::{cache::CacheMetadata, FilesystemResult};
+
+/// HTTP client used to download Aura files, built from [`HttpOptions`].
+///
+/// Cloning it is cheap: clones share the same underlying clients, so
+/// connections are reused by every request of a configuration.
+/// The clients are only built the first time they are needed.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    inner: Arc<Inner>
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    options: HttpOptions,
+    blocking: OnceLock<reqwest::blocking::Client>,
+    #[cfg(feature = "async")]
+    non_blocking: OnceLock<reqwest::Client>
+}
+
+/// Whether a failed request is worth retrying
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(status,
+        StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
+}
+
+/// Value of a `Range` header from `start` to `end` (inclusive)
+fn range_header(start: u64, end: Option<u64>) -> String {
+    match end {
+        Some(end) => format!("bytes={start}-{end}"),
+        None => format!("bytes={start}-"),
+    }
+}
+
+/// Headers of a request that asks whether a cached response changed
+fn conditional_headers(metadata: &CacheMetadata) -> Vec<(HeaderName, String)> {
+    let mut headers = Vec::new();
+    if let Some(etag) = &metadata.etag {
+        headers.push((IF_NONE_MATCH, etag.clone()));
+    }
+    if let Some(last_modified) = &metadata.last_modified {
+        headers.push((IF_MODIFIED_SINCE, last_modified.clone()));
+    }
+    headers
+}
+
+/// Turns a response status that isn't successful into an error
+fn check_status(url: &str, status: StatusCode) -> FilesystemResult<()> {
+    match status {
+        status if status.is_success() => Ok(()),
+        StatusCode::NOT_FOUND | StatusCode::GONE => Err(FilesystemError::NotFound(url.to_string())),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(FilesystemError::PermissionDenied(url.to_string())),
+        status => Err(FilesystemError::FetchError(url.to_string(), format!("the server responded with \"{status}\""), Some(status.as_u16()))),
+    }
+}
+
+impl HttpClient {
+    /// Constructs a client from its options
+    pub fn new(options: &HttpOptions) -> Self {
+        HttpClient { inner: Arc::new(Inner {
+            options: options.clone(),
+            blocking: OnceLock::new(),
+            #[cfg(feature = "async")]
+            non_blocking: OnceLock::new()
+        }) }
+    }
+
+    /// Returns the options this client was built from
+    pub fn options(&self) -> &HttpOptions {
+        &self.inner.options
+    }
+
+    /// Returns the `Authorization` header for a URL, if its host has a bearer token
+    fn authorization(&self, url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let token = self.options().bearer_token(url.host_str()?)?;
+        Some(format!("Bearer {token}"))
+    }
+
+    /// Returns the headers sent in every request to a URL
+    fn headers(&self, url: &str) -> Vec<(String, String)> {
+        let mut headers = self.options().headers().to_vec();
+        if let Some(authorization) = self.authorization(url) {
+            headers.push((AUTHORIZATION.to_string(), authorization));
+        }
+        headers
+    }
+
+    /// How long to wait before retrying for the `attempt`th time (starting at 1)
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.options().backoff().saturating_mul(2u32.saturating_pow(attempt - 1))
+    }
+
+    fn blocking(&self) -> FilesystemResult<&reqwest::blocking::Client> {
+        if let Some(client) = self.inner.blocking.get() {
+            return Ok(client);
+        }
+        let options = self.options();
+        let mut builder = reqwest::blocking::Client::builder().user_agent(options.user_agent());
+        if let Some(timeout) = options.timeout() {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+        let client = builder.build().map_err(|e| FilesystemError::FetchError(String::from("[client]"), e.to_string(), None))?;
+        Ok(self.inner.blocking.get_or_init(|| client))
+    }
+
+    /// Sends a GET request with some extra headers, retrying transient failures
+    /// as configured. Responses that aren't successful are returned as errors,
+    /// except for `304 Not Modified` if the request is `conditional`.
+    fn send(&self, url: &str, extra_headers: &[(HeaderName, String)], conditional: bool) -> FilesystemResult<reqwest::blocking::Response> {
+        let client = self.blocking()?;
+        let mut attempt = 0;
+        loop {
+            let mut request = client.get(url);
+            for (name, value) in self.headers(url) {
+                request = request.header(name, value);
+            }
+            for (name, value) in extra_headers {
+                request = request.header(name, value);
+            }
+
+            let retries_left = attempt < self.options().retries();
+            match request.send() {
+                Ok(response) if retries_left && is_transient_status(response.status()) => {},
+                Ok(response) if conditional && response.status() == StatusCode::NOT_MODIFIED => return Ok(response),
+                Ok(response) => return check_status(url, response.status()).map(|()| response),
+                Err(e) if retries_left && is_transient_error(&e) => {},
+                Err(e) => return Err(FilesystemError::FetchError(url.to_string(), e.to_string(), None)),
+            }
+            attempt += 1;
+            thread::sleep(self.backoff(attempt));
+        }
+    }
+
+    /// Fetches a web resource. Responses that aren't successful are returned
+    /// as errors: `404`/`410` as [`FilesystemError::NotFound`], `401`/`403` as
+    /// [`FilesystemError::PermissionDenied`] and anything else (including
+    /// `304 Not Modified`) as [`FilesystemError::FetchError`] with the status code.
+    pub fn get(&self, url: &str) -> FilesystemResult<reqwest::blocking::Response> {
+        self.send(url, &[], false)
+    }
+
+    /// Fetches the bytes of a web resource from `start` to `end` (inclusive) with
+    /// a `Range` header, or from `start` to the end of the resource if there's no `end`.
+    /// 
+    /// Servers are allowed to ignore the header and send the whole resource with
+    /// a `200 OK` status instead of `206 Partial Content`.
+    pub fn get_range(&self, url: &str, start: u64, end: Option<u64>) -> FilesystemResult<reqwest::blocking::Response> {
+        self.send(url, &[(RANGE, range_header(start, end))], false)
+    }
+
+    /// Fetches a web resource only if it changed since it was cached. The request
+    /// sends the `ETag` of the cached response as `If-None-Match` and its
+    /// `Last-Modified` date as `If-Modified-Since`.
+    /// 
+    /// If the resource didn't change, the response has a `304 Not Modified`
+    /// status and no body. Without validators, the request isn't conditional.
+    pub fn get_if_changed(&self, url: &str, metadata: &CacheMetadata) -> FilesystemResult<reqwest::blocking::Response> {
+        self.send(url, &conditional_headers(metadata), true)
+    }
+
+    #[cfg(feature = "async")]
+    fn non_blocking(&self) -> FilesystemResult<&reqwest::Client> {
+        if let Some(client) = self.inner.non_blocking.get() {
+            return Ok(client);
+        }
+        let options = self.options();
+        let mut builder = reqwest::Client::builder().user_agent(options.user_agent());
+        if let Some(timeout) = options.timeout() {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+        let client = builder.build().map_err(|e| FilesystemError::FetchError(String::from("[client]"), e.to_string(), None))?;
+        Ok(self.inner.non_blocking.get_or_init(|| client))
+    }
+
+    /// Asynchronous version of [`HttpClient::get`]
+    #[cfg(feature = "async")]
+    pub async fn get_async(&self, url: &str) -> FilesystemResult<reqwest::Response> {
+        self.send_async(url, &[], false).await
+    }
+
+    /// Asynchronous version of [`HttpClient::get_range`]
+    #[cfg(feature = "async")]
+    pub async fn get_range_async(&self, url: &str, start: u64, end: Option<u64>) -> FilesystemResult<reqwest::Response> {
+        self.send_async(url, &[(RANGE, range_header(start, end))], false).await
+    }
+
+    /// Asynchronous version of [`HttpClient::get_if_changed`]
+    #[cfg(feature = "async")]
+    pub async fn get_if_changed_async(&self, url: &str, metadata: &CacheMetadata) -> FilesystemResult<reqwest::Response> {
+        self.send_async(url, &conditional_headers(metadata), true).await
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_async(&self, url: &str, extra_headers: &[(HeaderName, String)], conditional: bool) -> FilesystemResult<reqwest::Response> {
+        let client = self.non_blocking()?;
+        let mut attempt = 0;
+        loop {
+            let mut request = client.get(url);
+            for (name, value) in self.headers(url) {
+                request = request.header(name, value);
+            }
+            for (name, value) in extra_headers {
+                request = request.header(name, value);
+            }
+
+            let retries_left = attempt < self.options().retries();
+            match request.send().await {
+                Ok(response) if retries_left && is_transient_status(response.status()) => {},
+                Ok(response) if conditional && response.status() == StatusCode::NOT_MODIFIED => return Ok(response),
+                Ok(response) => return check_status(url, response.status()).map(|()| response),
+                Err(e) if retries_left && is_transient_error(&e) => {},
+                Err(e) => return Err(FilesystemError::FetchError(url.to_string(), e.to_string(), None)),
+            }
+            attempt += 1;
+            tokio::time::sleep(self.backoff(attempt)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use crate::{config::options::HttpOptions, readers::{cache::CacheMetadata, http::{check_status, HttpClient}}, test_server::TestServer, FilesystemError, FilesystemResult};
+
+    #[test]
+    fn bearer_tokens_by_host() {
+        let client = HttpClient::new(&HttpOptions::new().with_bearer_token("CDN.example.com", "secret"));
+        assert_eq!(client.authorization("https://cdn.example.com/assets/a.png"), Some("Bearer secret".to_string()));
+        assert_eq!(client.authorization("https://cdn.example.com:8443/a.png"), Some("Bearer secret".to_string()));
+        assert_eq!(client.authorization("https://example.com/a.png"), None);
+        assert_eq!(client.authorization("https://other.cdn.example.com/a.png"), None);
+        assert_eq!(client.authorization("not a url"), None);
+    }
+
+    #[test]
+    fn status_errors() {
+        let url = "https://example.com/script.lua";
+        assert!(check_status(url, StatusCode::OK).is_ok());
+        assert!(check_status(url, StatusCode::PARTIAL_CONTENT).is_ok());
+        assert!(matches!(check_status(url, StatusCode::NOT_FOUND), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(check_status(url, StatusCode::GONE), Err(FilesystemError::NotFound(_))));
+        assert!(matches!(check_status(url, StatusCode::UNAUTHORIZED), Err(FilesystemError::PermissionDenied(_))));
+        assert!(matches!(check_status(url, StatusCode::FORBIDDEN), Err(FilesystemError::PermissionDenied(_))));
+        assert!(matches!(check_status(url, StatusCode::SERVICE_UNAVAILABLE), Err(FilesystemError::FetchError(_, _, Some(503)))));
+        assert!(matches!(check_status(url, StatusCode::MOVED_PERMANENTLY), Err(FilesystemError::FetchError(_, _, Some(301)))));
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let client = HttpClient::new(&HttpOptions::new().with_retries(3, Duration::from_millis(100)));
+        assert_eq!(client.backoff(1), Duration::from_millis(100));
+        assert_eq!(client.backoff(2), Duration::from_millis(200));
+        assert_eq!(client.backoff(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retry_unavailable_servers() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let patient = HttpClient::new(&HttpOptions::new().with_retries(2, Duration::from_millis(10)));
+        assert_eq!(patient.get(&server.url("/flaky.txt"))?.text().unwrap_or_default(), "Finally!");
+        assert_eq!(server.hits("/flaky.txt"), 3);
+
+        let server = TestServer::start();
+        let impatient = HttpClient::new(&HttpOptions::new().with_retries(1, Duration::from_millis(10)));
+        assert!(matches!(impatient.get(&server.url("/flaky.txt")), Err(FilesystemError::FetchError(_, _, Some(503)))));
+        assert_eq!(server.hits("/flaky.txt"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn send_headers() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let text = |response: reqwest::blocking::Response| response.text().unwrap_or_default();
+
+        assert!(matches!(HttpClient::default().get(&server.url("/private.txt")), Err(FilesystemError::PermissionDenied(_))));
+        let client = HttpClient::new(&HttpOptions::new()
+            .with_bearer_token("127.0.0.1", "secret")
+            .with_header("X-Game-Version", "1.2.0")
+            .with_user_agent("Obstruction/1.2.0"));
+        assert_eq!(text(client.get(&server.url("/private.txt"))?), "Top secret");
+        assert_eq!(text(client.get(&server.url("/echo/x-game-version"))?), "1.2.0");
+        assert_eq!(text(client.get(&server.url("/echo/user-agent"))?), "Obstruction/1.2.0");
+        assert_eq!(text(HttpClient::default().get(&server.url("/echo/user-agent"))?), concat!("oro-filesystem/", env!("CARGO_PKG_VERSION")));
+        assert_eq!(text(client.get_range(&server.url("/echo/range"), 10, None)?), "bytes=10-");
+        Ok(())
+    }
+
+    #[test]
+    fn not_modified_only_for_conditional_requests() -> FilesystemResult<()> {
+        let server = TestServer::start();
+        let client = HttpClient::default();
+        assert!(matches!(client.get(&server.url("/status/304")), Err(FilesystemError::FetchError(_, _, Some(304)))));
+        assert!(matches!(client.get_range(&server.url("/status/304"), 0, None), Err(FilesystemError::FetchError(_, _, Some(304)))));
+
+        let metadata = CacheMetadata { etag: Some(String::from("\"v1\"")), ..Default::default() };
+        assert_eq!(client.get_if_changed(&server.url("/versioned/v1"), &metadata)?.status(), StatusCode::NOT_MODIFIED);
+        Ok(())
+    }
+
+    #[test]
+    fn clones_share_the_client() {
+        let client = HttpClient::new(&HttpOptions::new());
+        let clone = client.clone();
+        assert!(std::ptr::eq(client.blocking().unwrap(), clone.blocking().unwrap()));
+    }
+}
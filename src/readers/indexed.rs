@@ -0,0 +1,383 @@
+use std::{collections::BTreeMap, sync::atomic::{AtomicUsize, Ordering}, thread};
+#[cfg(feature = "aura")]
+use std::{collections::HashMap, io::Cursor, sync::{Mutex, PoisonError}};
+
+use crate::{
+    backend::{Backend, BackendKind, DirEntry, Metadata, PrefetchEntry, PrefetchReport, ReadSeek},
+    config::{assetmap::AssetMap, index::{AssetPackIndex, IndexType}, virtual_path},
+    FilesystemError, FilesystemOptions, FilesystemType,
+};
+
+use super::{assetpackage, limits::ReadCounter, FilesystemResult};
+#[cfg(feature = "aura")]
+use super::{aura, cache::AuraCache, http::HttpClient, mirrors::MirrorHealth, transfer::Transfer};
+#[cfg(feature = "aura")]
+use crate::config::index::AuraIndex;
+#[cfg(feature = "async")]
+use crate::backend::BoxFuture;
+
+/// [`Backend`] for indexed filesystems. Every file is looked up in an
+/// [`AssetMap`] and read from an Asset Package or an Aura URL.
+#[derive(Debug)]
+pub struct IndexedBackend {
+    root: String,
+    index: AssetMap,
+    options: FilesystemOptions,
+    /// Shared by every Aura download of this backend
+    #[cfg(feature = "aura")]
+    client: HttpClient,
+    /// Aura files downloaded by [`Backend::prefetch`] when there's no cache
+    /// directory, keyed like the entries of an [`AuraCache`]. They're removed
+    /// when they're read.
+    #[cfg(feature = "aura")]
+    prefetched: Mutex<HashMap<String, Vec<u8>>>,
+    /// Failures of the hosts of Aura mirrors
+    #[cfg(feature = "aura")]
+    mirror_health: MirrorHealth
+}
+
+impl IndexedBackend {
+    /// Constructs a backend from an [`AssetMap`]. Asset Packages are
+    /// searched relative to `root`, which must end with `/`.
+    pub fn new(root: &str, index: AssetMap) -> Self {
+        IndexedBackend {
+            root: root.to_string(),
+            index,
+            options: FilesystemOptions::default(),
+            #[cfg(feature = "aura")]
+            client: HttpClient::default(),
+            #[cfg(feature = "aura")]
+            prefetched: Mutex::new(HashMap::new()),
+            #[cfg(feature = "aura")]
+            mirror_health: MirrorHealth::default()
+        }
+    }
+
+    /// Returns the index of a file or [`FilesystemError::NotFound`]
+    fn get_index(&self, path: &str) -> FilesystemResult<&IndexType> {
+        self.index.get(&virtual_path::normalize(path)?).ok_or(FilesystemError::NotFound(path.to_string()))
+    }
+
+    /// Returns the index of a file that is about to be read. The size of Asset
+    /// Package entries is counted against the size limits of the options
+    /// before anything is allocated.
+    /// 
+    /// The read must be settled with the returned counter, so the size is
+    /// refunded if it fails. Aura files count their own downloads.
+    /// 
+    /// Files of remote Asset Packages are never cached, so they fail with
+    /// [`FilesystemError::Offline`] in offline mode.
+    fn get_index_to_read(&self, path: &str) -> FilesystemResult<(&IndexType, ReadCounter)> {
+        let index = self.get_index(path)?;
+        let mut counter = ReadCounter::new(path, &self.options);
+        if let IndexType::AssetPack(asset_pack_index) = index {
+            self.check_online(asset_pack_index)?;
+            counter.add(asset_pack_index.file_size)?;
+        }
+        Ok((index, counter))
+    }
+
+    /// Fails with [`FilesystemError::Offline`] if an Asset Package is remote
+    /// and offline mode is enabled
+    fn check_online(&self, asset_pack_index: &AssetPackIndex) -> FilesystemResult<()> {
+        match asset_pack_index.is_remote() && self.options.is_offline() {
+            true => Err(FilesystemError::Offline(asset_pack_index.package.clone())),
+            false => Ok(()),
+        }
+    }
+
+    /// Error returned when reading Aura files and remote Asset Packages
+    /// without the `aura` feature
+    #[cfg(not(feature = "aura"))]
+    fn aura_disabled(path: &str) -> FilesystemError {
+        FilesystemError::FeatureDisabled(path.to_string(), String::from("aura"))
+    }
+
+    /// Takes the contents of an Aura file out of memory if it was prefetched.
+    /// Prefetched files are only kept until they're read for the first time.
+    #[cfg(feature = "aura")]
+    fn take_prefetched(&self, aura_index: &AuraIndex) -> Option<Vec<u8>> {
+        let key = AuraCache::key(&aura_index.url, aura_index.hash.as_deref()).ok()?;
+        self.prefetched.lock().unwrap_or_else(PoisonError::into_inner).remove(&key)
+    }
+
+    /// Reads an Aura file from its URL or, if that fails, from its mirrors
+    #[cfg(feature = "aura")]
+    fn read_mirrored<T>(&self, aura_index: &AuraIndex, read: impl FnMut(&str) -> FilesystemResult<T>) -> FilesystemResult<T> {
+        self.mirror_health.read(aura_index, self.options.mirror_ranking(), read)
+    }
+
+    /// Gets one file ready to be read. Only Aura files are downloaded: into the
+    /// cache directory if there is one, or into memory if there isn't. Files of
+    /// local Asset Packages are only checked, and remote ones always fail.
+    fn prefetch_one(&self, path: &str) -> FilesystemResult<()> {
+        match self.get_index(path)? {
+            #[cfg(not(feature = "aura"))]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+            #[cfg(feature = "aura")]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(FilesystemError::Generic(
+                path.to_string(),
+                String::from("Files of remote Asset Packages are never cached, so they can't be prefetched")
+            )),
+            IndexType::AssetPack(asset_pack_index) => assetpackage::open(path, &self.root, asset_pack_index).map(|_| ()),
+            #[cfg(feature = "aura")]
+            IndexType::Aura(aura_index) => {
+                let hash = aura_index.hash.as_deref();
+                if self.options.cache_dir().is_some() {
+                    // Downloaded files are stored in the cache while they're read
+                    return self.read_mirrored(aura_index, |url| aura::read(&self.client, url, hash, &self.options)).map(|_| ());
+                }
+                let key = AuraCache::key(&aura_index.url, hash)?;
+                if !self.prefetched.lock().unwrap_or_else(PoisonError::into_inner).contains_key(&key) {
+                    let binary = self.read_mirrored(aura_index, |url| aura::read(&self.client, url, hash, &self.options))?;
+                    self.prefetched.lock().unwrap_or_else(PoisonError::into_inner).insert(key, binary);
+                }
+                Ok(())
+            },
+            #[cfg(not(feature = "aura"))]
+            IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+        }
+    }
+
+    /// Checks if the normalized path is a directory. Directories are not stored in the
+    /// [`AssetMap`], so a path is a directory if any file is inside it.
+    fn is_dir(&self, directory: &str) -> bool {
+        directory.is_empty() || self.index.keys().any(|key| {
+            key.strip_prefix(directory).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+impl Backend for IndexedBackend {
+    fn read(&self, path: &str) -> FilesystemResult<Vec<u8>> {
+        let (index, counter) = self.get_index_to_read(path)?;
+        counter.settle(match index {
+            #[cfg(feature = "aura")]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => assetpackage::read_remote(path, &self.client, asset_pack_index, self.options.transfer()),
+            #[cfg(not(feature = "aura"))]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+            IndexType::AssetPack(asset_pack_index) => assetpackage::read(path, &self.root, asset_pack_index),
+            #[cfg(feature = "aura")]
+            IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                Some(binary) => Ok(binary),
+                None => self.read_mirrored(aura_index, |url| aura::read(&self.client, url, aura_index.hash.as_deref(), &self.options)),
+            },
+            #[cfg(not(feature = "aura"))]
+            IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+        })
+    }
+
+    fn read_to_string(&self, path: &str) -> FilesystemResult<String> {
+        let (index, counter) = self.get_index_to_read(path)?;
+        counter.settle(match index {
+            #[cfg(feature = "aura")]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => assetpackage::read_remote(path, &self.client, asset_pack_index, self.options.transfer())
+                .and_then(|buffer| String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))),
+            #[cfg(not(feature = "aura"))]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+            IndexType::AssetPack(asset_pack_index) => assetpackage::read_to_string(path, &self.root, asset_pack_index),
+            #[cfg(feature = "aura")]
+            IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                Some(binary) => String::from_utf8(binary).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string())),
+                None => self.read_mirrored(aura_index, |url| aura::read_to_string(&self.client, url, aura_index.hash.as_deref(), &self.options)),
+            },
+            #[cfg(not(feature = "aura"))]
+            IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+        })
+    }
+
+    #[cfg(feature = "aura")]
+    fn read_with(&self, path: &str, transfer: &Transfer) -> FilesystemResult<Vec<u8>> {
+        let (index, counter) = self.get_index_to_read(path)?;
+        counter.settle(match index {
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => {
+                assetpackage::read_remote(path, &self.client, asset_pack_index, &self.options.transfer().merged(transfer))
+            },
+            IndexType::AssetPack(asset_pack_index) => assetpackage::read(path, &self.root, asset_pack_index),
+            IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                Some(binary) => Ok(binary),
+                None => self.read_mirrored(aura_index, |url| aura::read_with(&self.client, url, aura_index.hash.as_deref(), &self.options, transfer)),
+            },
+        })
+    }
+
+    #[cfg(feature = "async")]
+    fn read_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<Vec<u8>>> {
+        Box::pin(async move {
+            let (index, counter) = self.get_index_to_read(path)?;
+            counter.settle(match index {
+                #[cfg(feature = "aura")]
+                IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => assetpackage::read_remote_async(path, &self.client, asset_pack_index, self.options.transfer()).await,
+                #[cfg(not(feature = "aura"))]
+                IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+                IndexType::AssetPack(asset_pack_index) => assetpackage::read_async(path, &self.root, asset_pack_index).await,
+                #[cfg(feature = "aura")]
+                IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                    Some(binary) => Ok(binary),
+                    None => {
+                        let hash = aura_index.hash.as_deref();
+                        self.mirror_health.read_async(aura_index, self.options.mirror_ranking(), |url| async move {
+                            aura::read_async(&self.client, &url, hash, &self.options).await
+                        }).await
+                    },
+                },
+                #[cfg(not(feature = "aura"))]
+                IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+            })
+        })
+    }
+
+    #[cfg(feature = "async")]
+    fn read_to_string_async<'a>(&'a self, path: &'a str) -> BoxFuture<'a, FilesystemResult<String>> {
+        Box::pin(async move {
+            let (index, counter) = self.get_index_to_read(path)?;
+            counter.settle(match index {
+                #[cfg(feature = "aura")]
+                IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => assetpackage::read_remote_async(path, &self.client, asset_pack_index, self.options.transfer()).await
+                    .and_then(|buffer| String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))),
+                #[cfg(not(feature = "aura"))]
+                IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+                IndexType::AssetPack(asset_pack_index) => assetpackage::read_async(path, &self.root, asset_pack_index).await
+                    .and_then(|buffer| String::from_utf8(buffer).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string()))),
+                #[cfg(feature = "aura")]
+                IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                    Some(binary) => String::from_utf8(binary).map_err(|e| FilesystemError::Generic(path.to_string(), e.to_string())),
+                    None => {
+                        let hash = aura_index.hash.as_deref();
+                        self.mirror_health.read_async(aura_index, self.options.mirror_ranking(), |url| async move {
+                            aura::read_to_string_async(&self.client, &url, hash, &self.options).await
+                        }).await
+                    },
+                },
+                #[cfg(not(feature = "aura"))]
+                IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+            })
+        })
+    }
+
+    fn open(&self, path: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        let (index, counter) = self.get_index_to_read(path)?;
+        counter.settle(match index {
+            #[cfg(feature = "aura")]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => assetpackage::open_remote(path, &self.client, asset_pack_index, self.options.transfer())
+                .map(|reader| Box::new(reader) as Box<dyn ReadSeek>),
+            #[cfg(not(feature = "aura"))]
+            IndexType::AssetPack(asset_pack_index) if asset_pack_index.is_remote() => Err(Self::aura_disabled(path)),
+            IndexType::AssetPack(asset_pack_index) => assetpackage::open(path, &self.root, asset_pack_index).map(|reader| Box::new(reader) as Box<dyn ReadSeek>),
+            #[cfg(feature = "aura")]
+            IndexType::Aura(aura_index) => match self.take_prefetched(aura_index) {
+                Some(binary) => Ok(Box::new(Cursor::new(binary))),
+                None => self.read_mirrored(aura_index, |url| aura::open(&self.client, url, aura_index.hash.as_deref(), &self.options)),
+            },
+            #[cfg(not(feature = "aura"))]
+            IndexType::Aura(_) => Err(Self::aura_disabled(path)),
+        })
+    }
+
+    fn metadata(&self, path: &str) -> FilesystemResult<Metadata> {
+        let normalized_path = virtual_path::normalize(path)?;
+        match self.index.get(&normalized_path) {
+            Some(IndexType::AssetPack(asset_pack_index)) => Ok(Metadata {
+                size: Some(asset_pack_index.file_size),
+                kind: BackendKind::AssetPack,
+                package: Some(asset_pack_index.package.clone()),
+                offset: Some(asset_pack_index.starting_index),
+                ..Default::default()
+            }),
+            Some(IndexType::Aura(aura_index)) => Ok(Metadata {
+                kind: BackendKind::Aura,
+                url: Some(aura_index.url.clone()),
+                hash: aura_index.hash.clone(),
+                ..Default::default()
+            }),
+            None if self.is_dir(&normalized_path) => Ok(Metadata { is_dir: true, kind: BackendKind::Virtual, ..Default::default() }),
+            None => Err(FilesystemError::NotFound(path.to_string())),
+        }
+    }
+
+    fn read_dir(&self, path: &str) -> FilesystemResult<Vec<DirEntry>> {
+        let normalized_path = virtual_path::normalize(path)?;
+        let directory = normalized_path.as_str();
+        if self.index.contains_key(directory) {
+            return Err(FilesystemError::NotADirectory(path.to_string()));
+        }
+
+        // Keyed by name so every subdirectory is only listed once
+        let mut entries: BTreeMap<String, bool> = BTreeMap::new();
+        for key in self.index.keys() {
+            let rest = if directory.is_empty() {
+                key.as_str()
+            } else {
+                match key.strip_prefix(directory).and_then(|rest| rest.strip_prefix('/')) {
+                    Some(rest) => rest,
+                    None => continue,
+                }
+            };
+
+            match rest.split_once('/') {
+                Some((subdirectory, _)) => { entries.insert(subdirectory.to_string(), true); },
+                None => { entries.entry(rest.to_string()).or_insert(false); },
+            }
+        }
+
+        if entries.is_empty() && !directory.is_empty() {
+            return Err(FilesystemError::NotFound(path.to_string()));
+        }
+
+        Ok(entries.into_iter().map(|(name, is_dir)| DirEntry {
+            path: if directory.is_empty() { name.clone() } else { format!("{directory}/{name}") },
+            name,
+            is_dir
+        }).collect())
+    }
+
+    /// Downloads Aura files concurrently, up to
+    /// [`FilesystemOptions::max_concurrent_downloads`] at the same time.
+    fn prefetch(&self, paths: &[&str]) -> PrefetchReport {
+        let workers = self.options.max_concurrent_downloads().min(paths.len());
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<Option<FilesystemResult<()>>> = paths.iter().map(|_| None).collect();
+
+        let finished: Vec<(usize, FilesystemResult<()>)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers).map(|_| scope.spawn(|| {
+                let mut finished = Vec::new();
+                loop {
+                    let position = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(position) else { break };
+                    finished.push((position, self.prefetch_one(path)));
+                }
+                finished
+            })).collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap_or_default()).collect()
+        });
+        for (position, result) in finished {
+            results[position] = Some(result);
+        }
+
+        PrefetchReport {
+            entries: paths.iter().zip(results).map(|(path, result)| PrefetchEntry {
+                path: path.to_string(),
+                result: result.unwrap_or_else(|| Err(FilesystemError::Generic(path.to_string(), String::from("The download thread panicked"))))
+            }).collect()
+        }
+    }
+
+    fn fs_type(&self) -> FilesystemType {
+        FilesystemType::Indexed
+    }
+
+    /// Replaces the options. Files prefetched into memory are dropped,
+    /// they were downloaded with the previous options.
+    fn set_options(&mut self, options: &FilesystemOptions) {
+        self.options = options.clone();
+        #[cfg(feature = "aura")]
+        {
+            self.client = HttpClient::new(options.http());
+            self.prefetched.get_mut().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+    }
+
+    fn asset_map(&self) -> Option<&AssetMap> {
+        Some(&self.index)
+    }
+}
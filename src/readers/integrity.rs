@@ -0,0 +1,161 @@
+//! Aura files can contain the hash of every web resource, so a resource
+//! that was altered or truncated after the Aura file was generated can
+//! be detected.
+//! 
+//! Hashes are written as `<algorithm>:<hex digest>`. The only supported
+//! algorithm is SHA-256, for example:
+//! `sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f`
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use sha2::{Digest, Sha256};
+
+use crate::{FilesystemError, FilesystemResult};
+
+/// A hash from an index file that downloaded data must match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedHash {
+    /// Lowercase hex digest
+    digest: String
+}
+
+impl ExpectedHash {
+    /// Parses a hash in the `sha256:<hex digest>` format.
+    /// The `url` is only used in error messages.
+    pub fn parse(url: &str, hash: &str) -> FilesystemResult<Self> {
+        let (algorithm, digest) = hash
+            .split_once(':')
+            .ok_or(FilesystemError::Generic(url.to_string(), format!("Invalid hash \"{hash}\". Expected \"sha256:<hex digest>\"")))?;
+
+        if !algorithm.eq_ignore_ascii_case("sha256") {
+            return Err(FilesystemError::Generic(url.to_string(), format!("Unsupported hash algorithm \"{algorithm}\"")));
+        }
+        if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(FilesystemError::Generic(url.to_string(), format!("Invalid SHA-256 digest \"{digest}\"")));
+        }
+
+        Ok(ExpectedHash { digest: digest.to_ascii_lowercase() })
+    }
+
+    /// Checks that `data` matches this hash or returns [`FilesystemError::HashMismatch`]
+    pub fn verify(&self, url: &str, data: &[u8]) -> FilesystemResult<()> {
+        self.verify_digest(url, Sha256::digest(data).as_slice())
+    }
+
+    fn verify_digest(&self, url: &str, digest: &[u8]) -> FilesystemResult<()> {
+        let actual = to_hex(digest);
+        if actual != self.digest {
+            return Err(FilesystemError::HashMismatch(url.to_string(), format!("sha256:{}", self.digest), format!("sha256:{actual}")));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the hash of `data` in the format used by index files (`sha256:<hex digest>`)
+pub fn sha256(data: &[u8]) -> String {
+    format!("sha256:{}", to_hex(Sha256::digest(data).as_slice()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Checks `data` against an optional hash from an index file.
+pub fn verify(url: &str, hash: Option<&str>, data: &[u8]) -> FilesystemResult<()> {
+    match hash {
+        Some(hash) => ExpectedHash::parse(url, hash)?.verify(url, data),
+        None => Ok(()),
+    }
+}
+
+/// A reader that hashes everything that is read through it and checks the hash
+/// when the end of the data is reached. A mismatch is returned as an
+/// [`io::ErrorKind::InvalidData`] error that wraps [`FilesystemError::HashMismatch`].
+/// 
+/// The data can only be verified if it's read from start to end. Seeking back to
+/// the start restarts the verification, seeking anywhere else disables it
+/// until the reader seeks back to the start.
+pub struct VerifyingReader<R> {
+    inner: R,
+    url: String,
+    expected: ExpectedHash,
+    /// [`None`] if the data read so far isn't contiguous from the start
+    hasher: Option<Sha256>
+}
+
+impl<R> VerifyingReader<R> {
+    pub fn new(inner: R, url: &str, expected: ExpectedHash) -> Self {
+        VerifyingReader { inner, url: url.to_string(), expected, hasher: Some(Sha256::new()) }
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        if bytes_read > 0 {
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&buf[..bytes_read]);
+            }
+        } else if !buf.is_empty() {
+            // End of the data
+            if let Some(hasher) = self.hasher.take() {
+                self.expected
+                    .verify_digest(&self.url, hasher.finalize().as_slice())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Seek> Seek for VerifyingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.hasher = if position == 0 { Some(Sha256::new()) } else { None };
+        Ok(position)
+    }
+}
+
+impl<R> std::fmt::Debug for VerifyingReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerifyingReader").field("url", &self.url).field("expected", &self.expected).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::{readers::integrity::{sha256, verify, ExpectedHash, VerifyingReader}, FilesystemError};
+
+    const HELLO_WORLD_HASH: &str = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+
+    #[test]
+    fn verify_hashes() {
+        assert_eq!(sha256(b"Hello, World!"), HELLO_WORLD_HASH);
+        verify("url", Some(HELLO_WORLD_HASH), b"Hello, World!").unwrap();
+        verify("url", Some(&HELLO_WORLD_HASH.to_uppercase().replace("SHA256", "sha256")), b"Hello, World!").unwrap();
+        verify("url", None, b"Anything").unwrap();
+
+        assert!(matches!(verify("url", Some(HELLO_WORLD_HASH), b"Hello, World"), Err(FilesystemError::HashMismatch(_, _, _))));
+    }
+
+    #[test]
+    fn reject_invalid_hashes() {
+        assert!(ExpectedHash::parse("url", "dffd6021bb2bd5b0").is_err());
+        assert!(ExpectedHash::parse("url", "md5:65a8e27d8879283831b664bd8b7f0ad4").is_err());
+        assert!(ExpectedHash::parse("url", "sha256:xyz").is_err());
+    }
+
+    #[test]
+    fn verify_while_reading() {
+        let expected = ExpectedHash::parse("url", HELLO_WORLD_HASH).unwrap();
+        let mut reader = VerifyingReader::new(Cursor::new(b"Hello, World!".to_vec()), "url", expected.clone());
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+
+        let mut tampered = VerifyingReader::new(Cursor::new(b"Hello, Wrld!".to_vec()), "url", expected);
+        let error = tampered.read_to_end(&mut contents).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
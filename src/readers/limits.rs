@@ -0,0 +1,191 @@
+//! Size limits of reads.
+//!
+//! An index controls how much data is read: the size of Asset Package
+//! entries and the body of Aura URLs. The limits of
+//! [`crate::FilesystemOptions`] make sure that a malicious index can't
+//! exhaust the memory (or disk) of the program. Sizes that are known in
+//! advance are checked before anything is allocated, and downloads are
+//! counted while they're received.
+
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+#[cfg(feature = "aura")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{FilesystemError, FilesystemOptions};
+
+use super::FilesystemResult;
+
+/// Counts the bytes of one read against the per-read and total limits
+#[derive(Debug)]
+pub struct ReadCounter {
+    path: String,
+    max_read: Option<u64>,
+    max_total: Option<u64>,
+    /// Shared by every read with the same options
+    total: Arc<AtomicU64>,
+    /// Bytes of this read
+    read: u64
+}
+
+impl ReadCounter {
+    /// Starts counting a read. `path` is only used in errors.
+    pub fn new(path: &str, options: &FilesystemOptions) -> Self {
+        ReadCounter {
+            path: path.to_string(),
+            max_read: options.max_read_size(),
+            max_total: options.max_total_size(),
+            total: options.total_counter(),
+            read: 0
+        }
+    }
+
+    /// Checks a size that is known before reading (like the one in a
+    /// `Content-Length` header) without counting it
+    #[cfg(feature = "aura")]
+    pub fn check(&self, size: u64) -> FilesystemResult<()> {
+        if let Some(max_read) = self.max_read.filter(|max_read| self.read.saturating_add(size) > *max_read) {
+            return Err(FilesystemError::SizeLimitExceeded(self.path.clone(), max_read));
+        }
+        if let Some(max_total) = self.max_total.filter(|max_total| self.total.load(Ordering::Relaxed).saturating_add(size) > *max_total) {
+            return Err(FilesystemError::SizeLimitExceeded(self.path.clone(), max_total));
+        }
+        Ok(())
+    }
+
+    /// Counts bytes that are about to be read or were just received.
+    /// Bytes over a limit aren't counted.
+    pub fn add(&mut self, bytes: u64) -> FilesystemResult<()> {
+        if let Some(max_read) = self.max_read.filter(|max_read| self.read.saturating_add(bytes) > *max_read) {
+            return Err(FilesystemError::SizeLimitExceeded(self.path.clone(), max_read));
+        }
+        let total = self.total.fetch_add(bytes, Ordering::Relaxed).saturating_add(bytes);
+        if let Some(max_total) = self.max_total.filter(|max_total| total > *max_total) {
+            self.total.fetch_sub(bytes, Ordering::Relaxed);
+            return Err(FilesystemError::SizeLimitExceeded(self.path.clone(), max_total));
+        }
+        self.read += bytes;
+        Ok(())
+    }
+
+    /// Gives the bytes counted so far back to the total, because the read failed
+    pub fn refund(&mut self) {
+        self.total.fetch_sub(self.read, Ordering::Relaxed);
+        self.read = 0;
+    }
+
+    /// Returns the result of the read this counter belongs to, refunding
+    /// its bytes if it failed
+    pub fn settle<T>(mut self, result: FilesystemResult<T>) -> FilesystemResult<T> {
+        if result.is_err() {
+            self.refund();
+        }
+        result
+    }
+}
+
+/// A reader that fails when the data goes over the limits of a [`ReadCounter`].
+/// Violations are returned as [`io::Error`]s that wrap
+/// [`FilesystemError::SizeLimitExceeded`].
+/// 
+/// The furthest offset that was read is counted, so data that is read again
+/// after seeking back (like a looping stream) isn't counted twice.
+#[cfg(feature = "aura")]
+#[derive(Debug)]
+pub struct LimitedReader<R> {
+    inner: R,
+    counter: ReadCounter,
+    /// Offset of the next read
+    position: u64
+}
+
+#[cfg(feature = "aura")]
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, counter: ReadCounter) -> Self {
+        LimitedReader { inner, counter, position: 0 }
+    }
+
+    /// Gives the bytes read so far back to the total, see [`ReadCounter::refund`]
+    pub fn refund(&mut self) {
+        self.counter.refund();
+    }
+}
+
+#[cfg(feature = "aura")]
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.position += bytes_read as u64;
+        if self.position > self.counter.read {
+            self.counter.add(self.position - self.counter.read).map_err(io::Error::other)?;
+        }
+        Ok(bytes_read)
+    }
+}
+
+#[cfg(feature = "aura")]
+impl<R: Seek> Seek for LimitedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{readers::limits::ReadCounter, FilesystemError, FilesystemOptions};
+
+    #[test]
+    fn count_reads() {
+        let options = FilesystemOptions::new().with_max_read_size(10).with_max_total_size(25);
+        assert!(matches!(ReadCounter::new("a", &options).add(11), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+
+        ReadCounter::new("a", &options).add(10).unwrap();
+        let mut counter = ReadCounter::new("b", &options);
+        counter.add(6).unwrap();
+        counter.add(4).unwrap();
+        assert!(matches!(counter.add(1), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+        assert_eq!(options.total_read(), 20);
+        assert!(matches!(ReadCounter::new("c", &options).add(10), Err(FilesystemError::SizeLimitExceeded(_, 25))));
+        // Rejected bytes aren't counted
+        assert_eq!(options.total_read(), 20);
+
+        // Nor are the bytes of failed reads
+        let mut counter = ReadCounter::new("d", &options);
+        counter.add(5).unwrap();
+        assert!(counter.settle::<()>(Err(FilesystemError::NotFound(String::from("d")))).is_err());
+        assert_eq!(options.total_read(), 20);
+
+        options.reset_total_read();
+        assert_eq!(options.total_read(), 0);
+    }
+
+    #[cfg(feature = "aura")]
+    #[test]
+    fn limit_downloads() {
+        use std::io::{Cursor, Read, Seek, SeekFrom};
+
+        use crate::readers::limits::LimitedReader;
+
+        let options = FilesystemOptions::new().with_max_read_size(10).with_max_total_size(15);
+        assert!(ReadCounter::new("a", &options).check(10).is_ok());
+        assert!(matches!(ReadCounter::new("a", &options).check(11), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+        assert!(matches!(ReadCounter::new("a", &options).check(u64::MAX), Err(FilesystemError::SizeLimitExceeded(_, 10))));
+
+        let mut contents = Vec::new();
+        LimitedReader::new(Cursor::new(vec![0u8; 8]), ReadCounter::new("b", &options)).read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len(), 8);
+        let error = LimitedReader::new(Cursor::new(vec![0u8; 8]), ReadCounter::new("c", &options)).read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(error.into_inner().unwrap().downcast::<FilesystemError>().is_ok_and(|e| matches!(*e, FilesystemError::SizeLimitExceeded(_, 15))));
+
+        // Data that is read again after seeking back is only counted once
+        options.reset_total_read();
+        let mut reader = LimitedReader::new(Cursor::new(vec![0u8; 8]), ReadCounter::new("d", &options));
+        for _ in 0..3 {
+            reader.rewind().unwrap();
+            reader.read_to_end(&mut Vec::new()).unwrap();
+        }
+        reader.seek(SeekFrom::Start(4)).unwrap();
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(options.total_read(), 8);
+    }
+}
@@ -0,0 +1,145 @@
+//! Fallback between the URL of an Aura entry and its mirrors.
+//!
+//! Every URL is tried in order until one of them can be read. Reads verify
+//! the hash of the entry, so data from a mirror that doesn't match it is
+//! never returned: the next mirror is tried instead.
+//!
+//! With [`crate::FilesystemOptions::with_mirror_ranking`], hosts that failed
+//! recently are tried after the ones that didn't.
+
+use std::{collections::HashMap, sync::{Mutex, PoisonError}};
+
+use reqwest::Url;
+
+use crate::{config::index::AuraIndex, FilesystemError};
+
+use super::FilesystemResult;
+
+/// Consecutive failures of every host, shared by every read of a backend
+#[derive(Debug, Default)]
+pub struct MirrorHealth {
+    failures: Mutex<HashMap<String, u32>>
+}
+
+impl MirrorHealth {
+    /// Returns the URLs of an entry in the order in which they're tried. With `ranked`,
+    /// URLs of hosts with fewer consecutive failures go first. Ties keep the
+    /// order of the index.
+    pub fn order<'a>(&self, aura_index: &'a AuraIndex, ranked: bool) -> Vec<&'a str> {
+        let mut urls: Vec<&str> = aura_index.urls().collect();
+        if ranked {
+            let failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+            urls.sort_by_key(|url| failures.get(&host_of(url)).copied().unwrap_or(0));
+        }
+        urls
+    }
+
+    /// Records the result of reading a URL
+    pub fn record<T>(&self, url: &str, result: &FilesystemResult<T>) {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        match result {
+            Ok(_) => { failures.remove(&host_of(url)); },
+            Err(_) => *failures.entry(host_of(url)).or_insert(0) += 1,
+        }
+    }
+
+    /// Reads an entry from its URL or, if that fails, from its mirrors.
+    ///
+    /// Returns the first successful read, or the error of the last URL if
+    /// every URL failed. Cancelled downloads are never retried with a mirror.
+    pub fn read<T>(&self, aura_index: &AuraIndex, ranked: bool, mut read: impl FnMut(&str) -> FilesystemResult<T>) -> FilesystemResult<T> {
+        let mut result = Err(FilesystemError::NotFound(aura_index.url.clone()));
+        for url in self.order(aura_index, ranked) {
+            result = read(url);
+            if aura_index.mirrors.is_empty() {
+                return result;
+            }
+            self.record(url, &result);
+            if !should_try_next(&result) {
+                break;
+            }
+        }
+        result
+    }
+
+    /// Asynchronous version of [`MirrorHealth::read`]
+    #[cfg(feature = "async")]
+    pub async fn read_async<T, F>(&self, aura_index: &AuraIndex, ranked: bool, mut read: impl FnMut(String) -> F) -> FilesystemResult<T>
+    where
+        F: std::future::Future<Output = FilesystemResult<T>>
+    {
+        let mut result = Err(FilesystemError::NotFound(aura_index.url.clone()));
+        for url in self.order(aura_index, ranked) {
+            result = read(url.to_string()).await;
+            if aura_index.mirrors.is_empty() {
+                return result;
+            }
+            self.record(url, &result);
+            if !should_try_next(&result) {
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// Every error is worth trying with another mirror, except for cancellations
+fn should_try_next<T>(result: &FilesystemResult<T>) -> bool {
+    matches!(result, Err(error) if !matches!(error, FilesystemError::Cancelled(_)))
+}
+
+/// Returns the host (and port) of a URL, or the whole URL if it doesn't have one
+fn host_of(url: &str) -> String {
+    Url::parse(url).ok()
+        .and_then(|parsed| Some(format!("{}:{}", parsed.host_str()?, parsed.port_or_known_default()?)))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::index::AuraIndex, readers::mirrors::MirrorHealth, FilesystemError};
+
+    fn entry() -> AuraIndex {
+        AuraIndex {
+            url: String::from("https://main.example.com/a.png"),
+            hash: None,
+            mirrors: vec![String::from("https://eu.example.com/a.png"), String::from("https://us.example.com/a.png")]
+        }
+    }
+
+    #[test]
+    fn try_mirrors_in_order() {
+        let health = MirrorHealth::default();
+        let mut tried = Vec::new();
+        let result = health.read(&entry(), false, |url| {
+            tried.push(url.to_string());
+            match url.contains("us.") {
+                true => Ok(url.to_string()),
+                false => Err(FilesystemError::NotFound(url.to_string())),
+            }
+        });
+        assert_eq!(result.unwrap(), "https://us.example.com/a.png");
+        assert_eq!(tried.len(), 3);
+
+        let cancelled: Result<(), _> = health.read(&entry(), false, |url| Err(FilesystemError::Cancelled(url.to_string())));
+        assert!(matches!(cancelled, Err(FilesystemError::Cancelled(url)) if url == "https://main.example.com/a.png"));
+    }
+
+    #[test]
+    fn rank_by_health() {
+        let health = MirrorHealth::default();
+        health.record::<()>("https://main.example.com/b.png", &Err(FilesystemError::NotFound(String::new())));
+        health.record::<()>("https://main.example.com/c.png", &Err(FilesystemError::NotFound(String::new())));
+        health.record::<()>("https://eu.example.com/c.png", &Err(FilesystemError::NotFound(String::new())));
+
+        assert_eq!(health.order(&entry(), false)[0], "https://main.example.com/a.png");
+        assert_eq!(health.order(&entry(), true), vec![
+            "https://us.example.com/a.png",
+            "https://eu.example.com/a.png",
+            "https://main.example.com/a.png"
+        ]);
+
+        health.record("https://main.example.com/a.png", &Ok(()));
+        assert_eq!(health.order(&entry(), true)[0], "https://main.example.com/a.png");
+    }
+}
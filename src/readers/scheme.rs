@@ -0,0 +1,214 @@
+use std::{fmt::Debug, fs::{self, File}, io::Cursor, path::{Path, PathBuf}};
+
+use reqwest::Url;
+
+use crate::{backend::ReadSeek, config::secure_path::BoundChecker, FilesystemError, FilesystemOptions};
+use super::FilesystemResult;
+
+/// Reads the resources of Aura URLs with a specific scheme, like `file` or
+/// a custom one such as `steam-workshop`. Handlers are registered with
+/// [`FilesystemOptions::with_scheme_handler`].
+///
+/// `http` and `https` URLs are downloaded with the HTTP client of the
+/// configuration unless a handler is registered for them. `data` URLs are
+/// always supported (see [`DataSchemeHandler`]).
+///
+/// Resources are read through [`SchemeHandler::open`] and count against the
+/// size limits of the options, like downloads.
+pub trait SchemeHandler: Debug + Send + Sync {
+    /// Reads the resource of a URL and returns its contents as binary
+    fn read(&self, url: &str) -> FilesystemResult<Vec<u8>>;
+
+    /// Opens the resource of a URL for reading.
+    ///
+    /// By default this calls [`SchemeHandler::read`] and keeps
+    /// the whole resource in memory.
+    fn open(&self, url: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        Ok(Box::new(Cursor::new(self.read(url)?)))
+    }
+}
+
+/// Returns the scheme of a URL in lowercase, or an empty string if it doesn't have one
+pub(crate) fn scheme_of(url: &str) -> String {
+    url.split_once(':').map(|(scheme, _)| scheme.to_ascii_lowercase()).unwrap_or_default()
+}
+
+/// Returns the handler for a URL, or [`None`] if the URL is downloaded
+/// with HTTP. Fails if no handler supports the scheme of the URL.
+pub(crate) fn handler_for<'a>(url: &str, options: &'a FilesystemOptions) -> FilesystemResult<Option<&'a dyn SchemeHandler>> {
+    let scheme = scheme_of(url);
+    if let Some(handler) = options.scheme_handler(&scheme) {
+        return Ok(Some(handler));
+    }
+    match scheme.as_str() {
+        "http" | "https" => Ok(None),
+        _ => Err(FilesystemError::FetchError(url.to_string(), format!("There's no handler for URLs with the \"{scheme}\" scheme"), None)),
+    }
+}
+
+/// Reads `file://` URLs from the native filesystem, for example local mirrors
+/// of a CDN. It isn't registered by default, because an index could use it to
+/// read any file of the system.
+#[derive(Debug, Default)]
+pub struct FileSchemeHandler {
+    bound_checker: Option<BoundChecker>
+}
+
+impl FileSchemeHandler {
+    /// Constructs a handler that can read any file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a handler that can only read files inside a directory.
+    /// Other files fail with [`FilesystemError::OutOfBounds`].
+    pub fn with_root(root: impl AsRef<Path>) -> FilesystemResult<Self> {
+        Ok(FileSchemeHandler { bound_checker: Some(BoundChecker::new(root.as_ref())?) })
+    }
+
+    fn to_path(&self, url: &str) -> FilesystemResult<PathBuf> {
+        let path = Url::parse(url).ok()
+            .and_then(|parsed| parsed.to_file_path().ok())
+            .ok_or(FilesystemError::FetchError(url.to_string(), String::from("Not a valid file URL"), None))?;
+        match &self.bound_checker {
+            Some(bound_checker) => bound_checker.ensure_in_bounds(&path),
+            None => Ok(path),
+        }
+    }
+}
+
+impl SchemeHandler for FileSchemeHandler {
+    fn read(&self, url: &str) -> FilesystemResult<Vec<u8>> {
+        fs::read(self.to_path(url)?).map_err(|e| FilesystemError::from(e).with_path(url.to_string()))
+    }
+
+    fn open(&self, url: &str) -> FilesystemResult<Box<dyn ReadSeek>> {
+        let file = File::open(self.to_path(url)?).map_err(|e| FilesystemError::from(e).with_path(url.to_string()))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// Reads `data:` URLs, which contain the resource itself, like
+/// `data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==`. Useful for tiny files.
+///
+/// It's registered by default.
+#[derive(Debug, Default)]
+pub struct DataSchemeHandler;
+
+impl SchemeHandler for DataSchemeHandler {
+    fn read(&self, url: &str) -> FilesystemResult<Vec<u8>> {
+        let to_error = |message: String| FilesystemError::FetchError(url.to_string(), message, None);
+        let data_url = data_url::DataUrl::process(url).map_err(|e| to_error(format!("Invalid data URL: {e:?}")))?;
+        let (body, _) = data_url.decode_to_vec().map_err(|e| to_error(format!("Invalid base64 in data URL: {e:?}")))?;
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{
+        config::{assetmap::AssetMap, index::{AuraIndex, IndexType}},
+        extra::IndexedBackend,
+        open, read, read_to_string,
+        readers::scheme::{handler_for, DataSchemeHandler, FileSchemeHandler, SchemeHandler},
+        FilesystemConfig, FilesystemError, FilesystemOptions, FilesystemResult
+    };
+
+    /// Resolves `steam-workshop://<item>/<file>` URLs like a game would
+    #[derive(Debug)]
+    struct WorkshopHandler;
+
+    impl SchemeHandler for WorkshopHandler {
+        fn read(&self, url: &str) -> FilesystemResult<Vec<u8>> {
+            match url.strip_prefix("steam-workshop://") {
+                Some("1234/readme.txt") => Ok(b"Downloaded from the workshop".to_vec()),
+                _ => Err(FilesystemError::NotFound(url.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn read_data_urls() -> FilesystemResult<()> {
+        assert_eq!(DataSchemeHandler.read("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==")?, b"Hello, World!");
+        assert_eq!(DataSchemeHandler.read("data:,Hello%2C%20World%21")?, b"Hello, World!");
+        assert!(DataSchemeHandler.read("data:;base64,not base64!").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn read_file_urls() -> FilesystemResult<()> {
+        let root = std::env::current_dir()?.join("tests/filesystem");
+        let file_url = |path: std::path::PathBuf| reqwest::Url::from_file_path(path).unwrap().to_string();
+        let url = file_url(root.join("testfile.txt"));
+
+        assert_eq!(FileSchemeHandler::new().read(&url)?, b"Hello, World!");
+        let mut contents = String::new();
+        FileSchemeHandler::with_root(&root)?.open(&url)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hello, World!");
+
+        let outside = file_url(std::env::current_dir()?.join("README.md"));
+        assert!(matches!(FileSchemeHandler::with_root(&root)?.read(&outside), Err(FilesystemError::OutOfBounds(_, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn read_aura_without_network() -> FilesystemResult<()> {
+        let aura = |url: &str, hash: Option<&str>| IndexType::Aura(AuraIndex { url: url.to_string(), hash: hash.map(str::to_string), mirrors: Vec::new() });
+        let mut index = AssetMap::new();
+        index.insert("inline.txt".to_string(), aura("data:,Hello%2C%20World%21", Some("sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f")));
+        index.insert("tampered.txt".to_string(), aura("data:,Hello%2C%20World%3F", Some("sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f")));
+        index.insert("workshop/readme.txt".to_string(), aura("steam-workshop://1234/readme.txt", None));
+        index.insert("workshop/missing.txt".to_string(), aura("steam-workshop://1234/missing.txt", None));
+
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index))
+            .with_options(FilesystemOptions::new().with_scheme_handler("steam-workshop", WorkshopHandler));
+
+        assert_eq!(read_to_string("inline.txt", &config)?, "Hello, World!");
+        assert!(matches!(read("tampered.txt", &config), Err(FilesystemError::HashMismatch(_, _, _))));
+        assert_eq!(read_to_string("workshop/readme.txt", &config)?, "Downloaded from the workshop");
+        assert!(matches!(read("workshop/missing.txt", &config), Err(FilesystemError::NotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn limit_handlers() -> FilesystemResult<()> {
+        let aura = |url: &str| IndexType::Aura(AuraIndex { url: url.to_string(), hash: None, mirrors: Vec::new() });
+        let mut index = AssetMap::new();
+        index.insert("readme.txt".to_string(), aura("steam-workshop://1234/readme.txt"));
+        index.insert("zero.bin".to_string(), aura("file:///dev/zero"));
+
+        let options = FilesystemOptions::new()
+            .with_scheme_handler("steam-workshop", WorkshopHandler)
+            .with_max_total_size(40);
+        let config = FilesystemConfig::with_backend(IndexedBackend::new("", index.clone())).with_options(options);
+
+        // Resources of scheme handlers are counted like downloads
+        assert_eq!(read_to_string("readme.txt", &config)?, "Downloaded from the workshop");
+        assert!(matches!(read("readme.txt", &config), Err(FilesystemError::SizeLimitExceeded(_, 40))));
+        assert!(open("readme.txt", &config)?.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(config.options().total_read(), 28);
+
+        // Endless resources stop at the limit
+        #[cfg(unix)]
+        {
+            let options = FilesystemOptions::new().with_scheme_handler("file", FileSchemeHandler::new()).with_max_read_size(1024 * 1024);
+            let config = FilesystemConfig::with_backend(IndexedBackend::new("", index)).with_options(options);
+            assert!(matches!(read("zero.bin", &config), Err(FilesystemError::SizeLimitExceeded(_, 1048576))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn choose_handler_by_scheme() -> FilesystemResult<()> {
+        let options = FilesystemOptions::new().with_scheme_handler("File", FileSchemeHandler::new());
+        assert!(handler_for("https://example.com/a.txt", &options)?.is_none());
+        assert!(handler_for("HTTP://example.com/a.txt", &options)?.is_none());
+        assert!(handler_for("data:,a", &options)?.is_some());
+        assert!(handler_for("file:///tmp/a.txt", &options)?.is_some());
+        assert!(handler_for("file:///tmp/a.txt", &FilesystemOptions::new()).is_err());
+        assert!(handler_for("steam-workshop://1234/a.txt", &options).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Progress and cancellation of downloads.
+//!
+//! A [`Transfer`] holds the [`ProgressObserver`]s that are notified while
+//! a file is downloaded and the [`CancellationToken`]s that can stop it.
+//! It can be set on the [`crate::FilesystemOptions`] of a configuration
+//! (for every download) or passed to [`crate::read_with`] (for one read).
+
+use std::{
+    fmt::Debug,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+
+use crate::FilesystemError;
+
+use super::FilesystemResult;
+
+/// Receives the progress of downloads.
+///
+/// It's implemented for every `Fn(&str, u64, Option<u64>)` closure,
+/// which receives the same parameters as [`ProgressObserver::on_progress`].
+pub trait ProgressObserver: Send + Sync {
+    /// Called every time a part of a download arrives. `received` is the
+    /// number of bytes received so far and `total` is the size of the
+    /// resource, if the server sent a `Content-Length` header.
+    fn on_progress(&self, url: &str, received: u64, total: Option<u64>);
+}
+
+impl<F: Fn(&str, u64, Option<u64>) + Send + Sync> ProgressObserver for F {
+    fn on_progress(&self, url: &str, received: u64, total: Option<u64>) {
+        self(url, received, total)
+    }
+}
+
+/// Stops downloads from another thread. Clones share the same state, so
+/// cancelling any clone cancels every download that uses the token.
+///
+/// Cancelled downloads fail with [`FilesystemError::Cancelled`]. A token
+/// can't be reset: once it's cancelled, every new download that uses it
+/// fails immediately.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>
+}
+
+impl CancellationToken {
+    /// Constructs a token that isn't cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every download that uses this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The progress observers and cancellation tokens of a download
+#[derive(Clone, Default)]
+pub struct Transfer {
+    observers: Vec<Arc<dyn ProgressObserver>>,
+    tokens: Vec<CancellationToken>
+}
+
+impl Transfer {
+    /// Constructs a transfer without observers or tokens
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notifies an observer of the progress of the download
+    pub fn with_progress(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Stops the download when the token is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.tokens.push(token);
+        self
+    }
+
+    /// Returns `true` if there are no observers and no tokens
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty() && self.tokens.is_empty()
+    }
+
+    /// Returns a transfer with the observers and tokens of both transfers
+    pub(crate) fn merged(&self, other: &Transfer) -> Transfer {
+        Transfer {
+            observers: self.observers.iter().chain(&other.observers).cloned().collect(),
+            tokens: self.tokens.iter().chain(&other.tokens).cloned().collect()
+        }
+    }
+
+    /// Fails with [`FilesystemError::Cancelled`] if any token was cancelled
+    pub(crate) fn check(&self, url: &str) -> FilesystemResult<()> {
+        match self.tokens.iter().any(CancellationToken::is_cancelled) {
+            true => Err(FilesystemError::Cancelled(url.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    /// Notifies every observer
+    pub(crate) fn report(&self, url: &str, received: u64, total: Option<u64>) {
+        for observer in &self.observers {
+            observer.on_progress(url, received, total);
+        }
+    }
+}
+
+impl Debug for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transfer").field("observers", &self.observers.len()).field("tokens", &self.tokens).finish()
+    }
+}
+
+/// A reader that reports the progress of a download and stops it when
+/// it's cancelled. Cancellations are returned as [`io::Error`]s that wrap
+/// [`FilesystemError::Cancelled`] (see [`from_io_error`]).
+#[derive(Debug)]
+pub struct TransferReader<R> {
+    inner: R,
+    url: String,
+    transfer: Transfer,
+    received: u64,
+    total: Option<u64>
+}
+
+impl<R> TransferReader<R> {
+    /// Wraps the body of a download from `url`. Progress is reported to the
+    /// observers of `transfer` with `total` as the expected size, if it's known.
+    pub fn new(inner: R, url: &str, transfer: &Transfer, total: Option<u64>) -> Self {
+        TransferReader { inner, url: url.to_string(), transfer: transfer.clone(), received: 0, total }
+    }
+}
+
+impl<R: Read> Read for TransferReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.transfer.check(&self.url).map_err(io::Error::other)?;
+        let bytes_read = self.inner.read(buf)?;
+        if bytes_read > 0 {
+            self.received += bytes_read as u64;
+            self.transfer.report(&self.url, self.received, self.total);
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Seek> Seek for TransferReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Converts an error of a [`TransferReader`] (or any reader that wraps
+/// a [`FilesystemError`] in an [`io::Error`]) back into a [`FilesystemError`]
+pub(crate) fn from_io_error(url: &str, error: io::Error) -> FilesystemError {
+    let kind = error.kind();
+    match error.into_inner().map(|inner| inner.downcast::<FilesystemError>()) {
+        Some(Ok(error)) => *error,
+        Some(Err(inner)) => FilesystemError::FetchError(url.to_string(), inner.to_string(), None),
+        None => FilesystemError::FetchError(url.to_string(), kind.to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{Cursor, Read}, sync::{Arc, Mutex}};
+
+    use crate::{readers::transfer::{from_io_error, CancellationToken, Transfer, TransferReader}, FilesystemError};
+
+    #[test]
+    fn report_and_cancel() {
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let observed = progress.clone();
+        let token = CancellationToken::new();
+        let transfer = Transfer::new()
+            .with_progress(move |_: &str, received, total| observed.lock().unwrap().push((received, total)))
+            .with_cancellation(token.clone());
+
+        let mut reader = TransferReader::new(Cursor::new(vec![0u8; 10]), "url", &transfer, Some(10));
+        let mut buffer = [0u8; 4];
+        reader.read_exact(&mut buffer).unwrap();
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(*progress.lock().unwrap(), vec![(4, Some(10)), (8, Some(10))]);
+
+        token.cancel();
+        let error = reader.read(&mut buffer).unwrap_err();
+        assert!(matches!(from_io_error("url", error), FilesystemError::Cancelled(_)));
+        assert!(matches!(Transfer::new().merged(&transfer).check("url"), Err(FilesystemError::Cancelled(_))));
+        assert!(Transfer::new().check("url").is_ok());
+    }
+}
@@ -0,0 +1,220 @@
+//! A small HTTP server for tests. It's started in-process on localhost,
+//! so Aura tests don't need the network.
+//!
+//! It serves the content of `tests/aura/webmod.oroi` (with its URLs pointing
+//! to this server instead of pastebin) and a few routes to test how
+//! downloads behave:
+//!
+//! - `/webmod.oroi` and the two files it indexes, `/raw/t0qjYDWt` and `/raw/eQe9aqfZ`
+//! - `/package.oap`: `tests/assetpackage/package.oap`, with support for `Range` headers
+//! - `/no-range/package.oap`: the same package from a server that ignores `Range` headers
+//! - `/large.bin`: 4 MiB of generated data, with support for `Range` headers
+//! - `/unsized/large.bin`: the same data without a `Content-Length` header
+//! - `/slow.txt`: waits one second before responding
+//! - `/status/<code>`: responds with that status code
+//! - `/flaky.txt`: responds with `503` twice, then works
+//! - `/private.txt`: needs an `Authorization: Bearer secret` header
+//! - `/echo/<header>`: responds with the value of a request header
+//! - `/versioned/<tag>`: responds with `Version <tag>` and an `ETag` (`"<tag>"`)
+//!   and a [`LAST_MODIFIED`] date, or with `304 Not Modified` to conditional
+//!   requests that match them
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+/// Contents of `/raw/t0qjYDWt`
+pub const TEXT: &str = "Hello, if you fetched this file from an Aura file, that means that ORO Filesystem is working!!";
+/// Contents of `/raw/eQe9aqfZ`
+pub const BINARY: &[u8] = b"Hello, World!";
+/// `Last-Modified` header of `/versioned/<tag>`
+pub const LAST_MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+/// Size of `/large.bin`
+pub const LARGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Contents of `/large.bin`
+pub fn large_body() -> Vec<u8> {
+    (0..LARGE_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+/// A request received by the [`TestServer`]
+struct Request {
+    path: String,
+    /// Header names are lowercase
+    headers: HashMap<String, String>
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Whether the response has a `Content-Length` header. Without it the
+    /// end of the body is the end of the connection.
+    sized: bool
+}
+
+impl Response {
+    fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Response { status, headers: Vec::new(), body: body.into(), sized: true }
+    }
+
+    /// Responds with a part of `body` if the request has a `Range` header
+    fn ranged(request: &Request, body: Vec<u8>) -> Self {
+        let Some(range) = request.headers.get("range").and_then(|range| range.strip_prefix("bytes=")) else {
+            return Response::new(200, body);
+        };
+        let total = body.len() as u64;
+        let (start, end) = range.split_once('-').unwrap_or((range, ""));
+        let start: u64 = start.parse().unwrap_or(0);
+        let end: u64 = end.parse().unwrap_or(u64::MAX).min(total.saturating_sub(1));
+        if start >= total || start > end {
+            return Response::new(416, "Range Not Satisfiable");
+        }
+
+        let mut response = Response::new(206, &body[start as usize..=end as usize]);
+        response.headers.push(("Content-Range".to_string(), format!("bytes {start}-{end}/{total}")));
+        response
+    }
+}
+
+/// An HTTP server that runs until the end of the test process
+pub struct TestServer {
+    address: SocketAddr,
+    hits: Arc<Mutex<HashMap<String, usize>>>
+}
+
+impl TestServer {
+    /// Starts a server in a random port
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't start the test server");
+        let address = listener.local_addr().expect("The test server has no address");
+        let hits: Arc<Mutex<HashMap<String, usize>>> = Arc::default();
+
+        let base_url = format!("http://{address}");
+        let server_hits = hits.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let base_url = base_url.clone();
+                let hits = server_hits.clone();
+                thread::spawn(move || handle(stream, &base_url, &hits));
+            }
+        });
+
+        TestServer { address, hits }
+    }
+
+    /// Returns the URL of a path in this server, like `http://127.0.0.1:1234/path`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.address)
+    }
+
+    /// Returns how many requests this server received for a path
+    pub fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap_or_else(PoisonError::into_inner).get(path).copied().unwrap_or(0)
+    }
+}
+
+fn handle(mut stream: TcpStream, base_url: &str, hits: &Mutex<HashMap<String, usize>>) {
+    let Some(request) = read_request(&stream) else { return };
+    let hit = {
+        let mut hits = hits.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = hits.entry(request.path.clone()).or_insert(0);
+        *count += 1;
+        *count
+    };
+
+    let response = respond(&request, base_url, hit);
+
+    let mut head = format!("HTTP/1.1 {} Test\r\nConnection: close\r\n", response.status);
+    if response.sized {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    // The client can hang up at any moment (for example, after a seek)
+    let _ = stream.write_all(head.as_bytes()).and_then(|()| stream.write_all(&response.body));
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let path = request_line.split_whitespace().nth(1)?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    Some(Request { path, headers })
+}
+
+/// `hit` is the number of requests received for this path, including this one
+fn respond(request: &Request, base_url: &str, hit: usize) -> Response {
+    match request.path.as_str() {
+        "/webmod.oroi" => {
+            let index = fs::read_to_string("tests/aura/webmod.oroi").expect("Couldn't read tests/aura/webmod.oroi");
+            Response::new(200, index.replace("https://pastebin.com", base_url))
+        },
+        "/raw/t0qjYDWt" => Response::new(200, TEXT),
+        "/raw/eQe9aqfZ" => Response::new(200, BINARY),
+        "/package.oap" => Response::ranged(request, fs::read("tests/assetpackage/package.oap").expect("Couldn't read tests/assetpackage/package.oap")),
+        "/no-range/package.oap" => Response::new(200, fs::read("tests/assetpackage/package.oap").expect("Couldn't read tests/assetpackage/package.oap")),
+        "/large.bin" => Response::ranged(request, large_body()),
+        "/unsized/large.bin" => Response { sized: false, ..Response::new(200, large_body()) },
+        "/slow.txt" => {
+            thread::sleep(Duration::from_secs(1));
+            Response::new(200, "Sorry for the wait")
+        },
+        "/flaky.txt" if hit <= 2 => Response::new(503, "Service Unavailable"),
+        "/flaky.txt" => Response::new(200, "Finally!"),
+        "/private.txt" => match request.headers.get("authorization").map(String::as_str) {
+            Some("Bearer secret") => Response::new(200, "Top secret"),
+            _ => Response::new(401, "Unauthorized"),
+        },
+        path => {
+            if let Some(code) = path.strip_prefix("/status/").and_then(|code| code.parse().ok()) {
+                return Response::new(code, "<html>This is an error page</html>");
+            }
+            if let Some(tag) = path.strip_prefix("/versioned/") {
+                return versioned(request, tag);
+            }
+            if let Some(header) = path.strip_prefix("/echo/") {
+                return Response::new(200, request.headers.get(header).cloned().unwrap_or_default());
+            }
+            Response::new(404, "<html>Not Found</html>")
+        },
+    }
+}
+
+/// Responds to `/versioned/<tag>`. `If-None-Match` takes precedence over
+/// `If-Modified-Since`, like in real servers.
+fn versioned(request: &Request, tag: &str) -> Response {
+    let etag = format!("\"{tag}\"");
+    let not_modified = match request.headers.get("if-none-match") {
+        Some(if_none_match) => *if_none_match == etag,
+        None => request.headers.get("if-modified-since").is_some_and(|date| date == LAST_MODIFIED),
+    };
+    let mut response = match not_modified {
+        true => Response::new(304, ""),
+        false => Response::new(200, format!("Version {tag}")),
+    };
+    response.headers.push(("ETag".to_string(), etag));
+    response.headers.push(("Last-Modified".to_string(), LAST_MODIFIED.to_string()));
+    response
+}